@@ -0,0 +1,149 @@
+//! Stable error codes for N-API exports.
+//!
+//! # Overview
+//! Errors raised by glob, the fs scan cache, PTY sessions, and cancellation
+//! carry an [`ErrorCode`] so JS callers can branch on `err.code` instead of
+//! matching message text.
+//!
+//! The code travels inside a regular [`napi::Error`] (so existing `Result`
+//! plumbing is unchanged) and is promoted to the JS `code` property by
+//! [`to_js_error`] when the error crosses back onto the JS thread.
+//!
+//! # Example
+//! ```ignore
+//! // JS: try { await native.glob(...) } catch (err) { if (err.code === "PATH_NOT_FOUND") ... }
+//! ```
+
+use napi::{
+	Env, Error, Status,
+	bindgen_prelude::{FromNapiValue, ToNapiValue, Unknown},
+};
+
+/// Prefix marking the carrier cause that holds an [`ErrorCode`].
+const CODE_MARKER: &str = "pi-natives:code:";
+
+/// Machine-readable error category exposed to JS as `err.code`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+	/// Search path does not exist or cannot be read.
+	PathNotFound,
+	/// Search path exists but is not a directory.
+	NotADirectory,
+	/// Glob pattern failed to parse or compile.
+	InvalidPattern,
+	/// Operation exceeded its timeout.
+	Timeout,
+	/// Operation was aborted by a signal or user request.
+	Aborted,
+	/// PTY session has no running command.
+	PtyNotRunning,
+	/// PTY session already has a running command.
+	PtyAlreadyRunning,
+	/// PTY could not be opened, spawned, or driven.
+	PtyFailure,
+	/// Filesystem I/O failed.
+	Io,
+	/// Search path is not inside a git repository.
+	NotAGitRepo,
+	/// Path resolves outside the base directory it must stay within.
+	PathOutsideBase,
+	/// Process-wide PTY session limit (`PTY_MAX_SESSIONS`) is exhausted.
+	PtyLimitReached,
+	/// Query matched nothing while `requireMatch` was set.
+	NoMatch,
+}
+
+impl ErrorCode {
+	const ALL: [Self; 13] = [
+		Self::PathNotFound,
+		Self::NotADirectory,
+		Self::InvalidPattern,
+		Self::Timeout,
+		Self::Aborted,
+		Self::PtyNotRunning,
+		Self::PtyAlreadyRunning,
+		Self::PtyFailure,
+		Self::Io,
+		Self::NotAGitRepo,
+		Self::PathOutsideBase,
+		Self::PtyLimitReached,
+		Self::NoMatch,
+	];
+
+	/// Stable string form, e.g. `"PATH_NOT_FOUND"`.
+	pub const fn as_str(self) -> &'static str {
+		match self {
+			Self::PathNotFound => "PATH_NOT_FOUND",
+			Self::NotADirectory => "NOT_A_DIRECTORY",
+			Self::InvalidPattern => "INVALID_PATTERN",
+			Self::Timeout => "TIMEOUT",
+			Self::Aborted => "ABORTED",
+			Self::PtyNotRunning => "PTY_NOT_RUNNING",
+			Self::PtyAlreadyRunning => "PTY_ALREADY_RUNNING",
+			Self::PtyFailure => "PTY_FAILURE",
+			Self::Io => "IO",
+			Self::NotAGitRepo => "NOT_A_GIT_REPO",
+			Self::PathOutsideBase => "PATH_OUTSIDE_BASE",
+			Self::PtyLimitReached => "PTY_LIMIT_REACHED",
+			Self::NoMatch => "NO_MATCH",
+		}
+	}
+
+	fn parse(value: &str) -> Option<Self> {
+		Self::ALL.into_iter().find(|code| code.as_str() == value)
+	}
+}
+
+impl AsRef<str> for ErrorCode {
+	fn as_ref(&self) -> &str {
+		self.as_str()
+	}
+}
+
+/// Create an error with `reason` tagged with `code`.
+pub fn coded(code: ErrorCode, reason: impl Into<String>) -> Error {
+	let mut error = Error::from_reason(reason);
+	error.cause =
+		Some(Box::new(Error::new(Status::GenericFailure, format!("{CODE_MARKER}{}", code.as_str()))));
+	error
+}
+
+/// The code attached by [`coded`], if any.
+pub fn code_of(error: &Error) -> Option<ErrorCode> {
+	error
+		.cause
+		.as_ref()
+		.and_then(|cause| cause.reason.strip_prefix(CODE_MARKER))
+		.and_then(ErrorCode::parse)
+}
+
+/// Promote a coded error to a JS error whose `code` is the [`ErrorCode`].
+///
+/// Must run on the JS thread. Uncoded errors are returned unchanged.
+pub fn to_js_error(env: &Env, error: Error) -> Error {
+	let Some(code) = code_of(&error) else {
+		return error;
+	};
+	let js_error = Error::new(code, error.reason.clone());
+	// SAFETY: `env` is the live environment of the current JS thread, and the
+	// value passed to `from_napi_value` was just created in it.
+	let value = unsafe {
+		ToNapiValue::to_napi_value(env.raw(), js_error)
+			.and_then(|value| Unknown::from_napi_value(env.raw(), value))
+	};
+	value.map_or(error, Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_code_round_trip() {
+		for code in ErrorCode::ALL {
+			assert_eq!(code_of(&coded(code, "boom")), Some(code));
+			assert_eq!(ErrorCode::parse(code.as_str()), Some(code));
+		}
+		assert_eq!(code_of(&Error::from_reason("plain")), None);
+	}
+}
@@ -65,7 +65,7 @@ pub struct FuzzyFindResult {
 
 /// Strips separators, whitespace, and punctuation for normalized fuzzy
 /// comparison.
-fn normalize_fuzzy_text(value: &str) -> String {
+pub fn normalize_fuzzy_text(value: &str) -> String {
 	value
 		.chars()
 		.filter(|ch| !ch.is_whitespace() && !matches!(ch, '/' | '\\' | '.' | '_' | '-'))
@@ -104,7 +104,7 @@ fn fuzzy_subsequence_score(query: &str, target: &str) -> u32 {
 }
 
 /// Composite path scoring: exact > starts-with > contains > fuzzy subsequence.
-fn score_fuzzy_path(
+pub fn score_fuzzy_path(
 	path: &str,
 	is_directory: bool,
 	query_lower: &str,
@@ -181,8 +181,10 @@ fn fuzzy_find_sync(config: FuzzyFindConfig, ct: task::CancelToken) -> Result<Fuz
 	}
 
 	let use_cache = config.cache.unwrap_or(false);
+	let scan_options = fs_cache::ScanOptions::new(include_hidden, respect_gitignore);
 	let mut scored = if use_cache {
-		let scan = fs_cache::get_or_scan(&root, include_hidden, respect_gitignore, &ct)?;
+		let scan =
+			fs_cache::get_or_scan(&root, scan_options, &[], fs_cache::SHARED_NAMESPACE, None, &ct)?;
 		let mut scored = score_entries(&scan.entries, &query_lower, &normalized_query, &ct)?;
 		// Empty-result recheck: if the query was non-trivial but produced zero matches
 		// from a cached scan that's old enough, force one rescan before giving up.
@@ -190,13 +192,29 @@ fn fuzzy_find_sync(config: FuzzyFindConfig, ct: task::CancelToken) -> Result<Fuz
 			&& !query_lower.is_empty()
 			&& scan.cache_age_ms >= fs_cache::empty_recheck_ms()
 		{
-			let fresh = fs_cache::force_rescan(&root, include_hidden, respect_gitignore, true, &ct)?;
-			scored = score_entries(&fresh, &query_lower, &normalized_query, &ct)?;
+			let fresh = fs_cache::force_rescan(
+				&root,
+				scan_options,
+				&[],
+				fs_cache::SHARED_NAMESPACE,
+				true,
+				None,
+				&ct,
+			)?;
+			scored = score_entries(&fresh.entries, &query_lower, &normalized_query, &ct)?;
 		}
 		scored
 	} else {
-		let fresh = fs_cache::force_rescan(&root, include_hidden, respect_gitignore, false, &ct)?;
-		score_entries(&fresh, &query_lower, &normalized_query, &ct)?
+		let fresh = fs_cache::force_rescan(
+			&root,
+			scan_options,
+			&[],
+			fs_cache::SHARED_NAMESPACE,
+			false,
+			None,
+			&ct,
+		)?;
+		score_entries(&fresh.entries, &query_lower, &normalized_query, &ct)?
 	};
 
 	scored.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.path.cmp(&b.path)));
@@ -4,25 +4,40 @@
 //! - Global policy (no per-call TTL tuning)
 //! - Explicit invalidation for agent file mutations
 //! - Empty-result fast recheck to avoid stale negatives
+//! - Invalidation epochs so scans in flight during a mutation are not cached
 //!
 //! # Policy Configuration (environment overrides)
 //! - `FS_SCAN_CACHE_TTL_MS`       – default `1000`
 //! - `FS_SCAN_EMPTY_RECHECK_MS`   – default `200`
-//! - `FS_SCAN_CACHE_MAX_ENTRIES`   – default `16`
+//! - `FS_SCAN_CACHE_MAX_ENTRIES`   – default `16`, per cache namespace
 
 use std::{
 	borrow::Cow,
+	collections::HashSet,
 	path::{Path, PathBuf},
-	sync::LazyLock,
+	sync::{
+		LazyLock,
+		atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+	},
 	time::{Duration, Instant},
 };
 
-use dashmap::DashMap;
-use ignore::WalkBuilder;
-use napi::bindgen_prelude::*;
+use dashmap::{DashMap, DashSet};
+use ignore::{
+	WalkBuilder, WalkState,
+	gitignore::{Gitignore, GitignoreBuilder},
+};
+use napi::{
+	bindgen_prelude::*,
+	threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode},
+};
 use napi_derive::napi;
+use parking_lot::Mutex;
 
-use crate::task;
+use crate::{
+	error::{self, ErrorCode},
+	task,
+};
 
 // ═══════════════════════════════════════════════════════════════════════════
 // Public types (re-exported by glob for backward compatibility)
@@ -44,13 +59,112 @@ pub enum FileType {
 #[napi(object)]
 pub struct GlobMatch {
 	/// Relative path from the search root, using forward slashes.
-	pub path:      String,
+	pub path:             String,
 	/// Resolved filesystem type for the match.
 	#[napi(js_name = "fileType")]
-	pub file_type: FileType,
+	pub file_type:        FileType,
 	/// Modification time in milliseconds since Unix epoch (from
 	/// `symlink_metadata`).
-	pub mtime:     Option<f64>,
+	pub mtime:            Option<f64>,
+	/// Number of matches summarized by this directory entry; only set when
+	/// `collapseThreshold` collapsed them.
+	#[napi(js_name = "collapsedCount")]
+	pub collapsed_count:  Option<u32>,
+	/// Fuzzy match score (higher is better); only set when `fuzzy` is used.
+	pub score:            Option<u32>,
+	/// Number of scanned immediate children of this directory; only set when
+	/// `includeChildCount` is enabled.
+	#[napi(js_name = "childCount")]
+	pub child_count:      Option<u32>,
+	/// Position of this entry in the scan's walk order; only set when
+	/// `includeOrderIndex` is enabled.
+	#[napi(js_name = "orderIndex")]
+	pub order_index:      Option<u32>,
+	/// Milliseconds from the start of the walk until it found this entry;
+	/// only set by fresh scans with `includeDiscoveredAtMs`.
+	#[napi(js_name = "discoveredAtMs")]
+	pub discovered_at_ms: Option<f64>,
+	/// Absolute, symlink-resolved path; only set with `canonicalize`, and
+	/// left unset when resolution fails (e.g. a dangling symlink).
+	#[napi(js_name = "realPath")]
+	pub real_path:        Option<String>,
+	/// Directories between the root and this entry (0 = immediate child), as
+	/// `exactDepth` counts them; only set when `includeDepth` is enabled.
+	pub depth:            Option<u32>,
+	/// Index of the pattern that matched this entry; only set when
+	/// `includeMatchedPatternIndex` is enabled.
+	#[napi(js_name = "patternIndex")]
+	pub pattern_index:    Option<u32>,
+}
+
+impl GlobMatch {
+	/// A scanned entry with no query annotations.
+	pub const fn new(path: String, file_type: FileType, mtime: Option<f64>) -> Self {
+		Self {
+			path,
+			file_type,
+			mtime,
+			collapsed_count: None,
+			score: None,
+			child_count: None,
+			order_index: None,
+			discovered_at_ms: None,
+			real_path: None,
+			depth: None,
+			pattern_index: None,
+		}
+	}
+}
+
+/// Walker settings that shape a scan; part of the cache key.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct ScanOptions {
+	/// Include hidden files.
+	pub include_hidden:    bool,
+	/// Honor `.gitignore` and related ignore sources.
+	pub use_gitignore:     bool,
+	/// Honor [`PIIGNORE_FILENAME`] files, independently of `use_gitignore`.
+	pub use_piignore:      bool,
+	/// Emit each physical file once, skipping later paths that resolve to an
+	/// already-seen `(dev, ino)` (canonical path on non-Unix platforms).
+	pub dedup_by_inode:    bool,
+	/// Descend into symlinked directories; loops are detected and skipped.
+	pub follow_links:      bool,
+	/// With `follow_links`, stop descending below a path once it has crossed
+	/// this many symlinks; the last symlink is still listed.
+	pub max_symlink_depth: Option<u32>,
+	/// Don't descend into directories on a different filesystem (device on
+	/// Unix, volume on Windows) than the root; mount points are still listed.
+	pub same_file_system:  bool,
+	/// Stop the walk after this many entries. Per-call limit; not part of the
+	/// cache key, and capped scans are never cached.
+	pub max_entries:       Option<usize>,
+	/// Walk with the parallel walker on this many threads (0 picks a count
+	/// automatically); `None` walks on the calling thread. Results are sorted
+	/// to match the sequential order. Not part of the cache key.
+	pub threads:           Option<usize>,
+	/// Stamp each entry's `discovered_at_ms`. Not part of the cache key; the
+	/// cache never keeps stamps, so cached entries have none.
+	pub record_discovery:  bool,
+}
+
+impl ScanOptions {
+	/// Scan options with the given visibility/ignore settings and no dedup or
+	/// entry cap. `.piignore` files are honored.
+	pub const fn new(include_hidden: bool, use_gitignore: bool) -> Self {
+		Self {
+			include_hidden,
+			use_gitignore,
+			use_piignore: true,
+			dedup_by_inode: false,
+			follow_links: false,
+			max_symlink_depth: None,
+			same_file_system: false,
+			max_entries: None,
+			threads: None,
+			record_discovery: false,
+		}
+	}
 }
 
 // ═══════════════════════════════════════════════════════════════════════════
@@ -60,6 +174,7 @@ pub struct GlobMatch {
 const DEFAULT_CACHE_TTL_MS: u64 = 1_000;
 const DEFAULT_EMPTY_RECHECK_MS: u64 = 200;
 const DEFAULT_MAX_CACHE_ENTRIES: usize = 16;
+const DEFAULT_MAX_CACHE_BYTES: usize = 256 * 1024 * 1024;
 
 fn env_u64(name: &str, default: u64) -> u64 {
 	std::env::var(name)
@@ -89,63 +204,257 @@ fn max_cache_entries() -> usize {
 	env_usize("FS_SCAN_CACHE_MAX_ENTRIES", DEFAULT_MAX_CACHE_ENTRIES)
 }
 
+#[cfg(test)]
+thread_local! {
+	/// Latency added before every walk on this thread, so tests can age cached
+	/// scans past the TTL or empty-recheck threshold without racing the clock.
+	pub static SCAN_TEST_DELAY: std::cell::Cell<Duration> =
+		const { std::cell::Cell::new(Duration::ZERO) };
+}
+
+/// Ceiling on the estimated size of all cached scans combined, across
+/// namespaces.
+fn max_cache_bytes() -> usize {
+	env_usize("FS_SCAN_CACHE_MAX_BYTES", DEFAULT_MAX_CACHE_BYTES)
+}
+
 // ═══════════════════════════════════════════════════════════════════════════
 // Cache internals
 // ═══════════════════════════════════════════════════════════════════════════
 
+/// Cache namespace used by tools that don't isolate their scans.
+pub const SHARED_NAMESPACE: &str = "";
+
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 struct CacheKey {
-	root:           PathBuf,
-	include_hidden: bool,
-	use_gitignore:  bool,
+	root:      PathBuf,
+	options:   ScanOptions,
+	/// `reinclude` lines the scan was extended with; see [`get_or_scan`].
+	reinclude: Vec<String>,
+	/// Independent eviction pool; see [`SHARED_NAMESPACE`].
+	namespace: String,
+}
+
+impl CacheKey {
+	/// Builds a key, dropping per-call settings that don't change scan contents.
+	fn new(root: &Path, options: ScanOptions, namespace: &str) -> Self {
+		Self {
+			root:      root.to_path_buf(),
+			options:   ScanOptions {
+				max_entries: None,
+				threads: None,
+				record_discovery: false,
+				..options
+			},
+			reinclude: Vec::new(),
+			namespace: namespace.to_string(),
+		}
+	}
 }
 
 #[derive(Clone)]
 struct CacheEntry {
-	created_at: Instant,
-	entries:    Vec<GlobMatch>,
+	created_at:        Instant,
+	entries:           Vec<GlobMatch>,
+	symlink_loops:     u32,
+	gitignore_applied: bool,
+	/// Estimated heap size of `entries`; see [`entries_bytes`].
+	bytes:             usize,
+}
+
+/// Approximate heap footprint of cached `entries`.
+fn entries_bytes(entries: &[GlobMatch]) -> usize {
+	entries
+		.iter()
+		.map(|entry| size_of::<GlobMatch>() + entry.path.capacity())
+		.sum()
 }
 
 static FS_CACHE: LazyLock<DashMap<CacheKey, CacheEntry>> = LazyLock::new(DashMap::new);
 
+/// Keys [`evict_oldest`] passes over while unpinned entries remain. Pins
+/// outlive invalidation, so a pinned root's rescans are protected too.
+static PINNED_KEYS: LazyLock<DashSet<CacheKey>> = LazyLock::new(DashSet::new);
+
+/// Global invalidation counter; advanced by every invalidation.
+static SCAN_EPOCH: AtomicU64 = AtomicU64::new(0);
+
+/// Epoch observed by scans of each root since it was last invalidated.
+///
+/// Invalidation advances [`SCAN_EPOCH`] and removes the affected roots, so a
+/// scan that started before the invalidation finds its snapshot missing (or
+/// replaced by a newer epoch) and skips caching its now-stale result.
+static ROOT_EPOCHS: LazyLock<DashMap<PathBuf, u64>> = LazyLock::new(DashMap::new);
+
+/// Snapshot the epoch of `root` before scanning it.
+fn root_epoch(root: &Path) -> u64 {
+	*ROOT_EPOCHS
+		.entry(root.to_path_buf())
+		.or_insert_with(|| SCAN_EPOCH.load(Ordering::SeqCst))
+}
+
+/// Invalidate the epoch of every root matching `affected`.
+///
+/// Must run before the matching cache entries are removed or rewritten.
+fn advance_epochs(affected: impl Fn(&Path) -> bool) {
+	SCAN_EPOCH.fetch_add(1, Ordering::SeqCst);
+	ROOT_EPOCHS.retain(|root, _| !affected(root));
+}
+
+/// Cache a scan of `key.root` unless the root was invalidated since `epoch`
+/// was taken, or the scan alone exceeds the global byte ceiling.
+fn store_scan(key: CacheKey, epoch: u64, created_at: Instant, scan: &ScanResult) {
+	let bytes = entries_bytes(&scan.entries);
+	if bytes > max_cache_bytes() {
+		return;
+	}
+	let mut entries = scan.entries.clone();
+	// Discovery stamps describe one walk; cache hits report none.
+	for entry in &mut entries {
+		entry.discovered_at_ms = None;
+	}
+	{
+		// Hold the epoch guard across the insert so a concurrent invalidation
+		// either sees the new entry or makes this check fail.
+		let Some(current) = ROOT_EPOCHS.get(&key.root) else {
+			return;
+		};
+		if *current != epoch {
+			return;
+		}
+		FS_CACHE.insert(key.clone(), CacheEntry {
+			created_at,
+			entries,
+			symlink_loops: scan.symlink_loops,
+			gitignore_applied: scan.gitignore_applied,
+			bytes,
+		});
+	}
+	notify_evicted(
+		evict_oldest(&FS_CACHE, &PINNED_KEYS, &key.namespace, max_cache_entries()),
+		"entryCap",
+	);
+	evict_over_budget();
+}
+
 /// Result of a cache-aware scan, including the age of the cached data.
+#[derive(Default)]
 pub struct ScanResult {
 	/// Scanned filesystem entries.
-	pub entries:      Vec<GlobMatch>,
+	pub entries:           Vec<GlobMatch>,
 	/// How old the cached data is in milliseconds (0 = freshly scanned).
-	pub cache_age_ms: u64,
+	pub cache_age_ms:      u64,
+	/// Whether the walk stopped at [`ScanOptions::max_entries`], leaving
+	/// `entries` incomplete.
+	pub capped:            bool,
+	/// Whether `entries` were served from the cache rather than walked.
+	pub from_cache:        bool,
+	/// Number of symlink loops the walker detected and skipped.
+	pub symlink_loops:     u32,
+	/// Whether any `.gitignore` or `.git/info/exclude` rules applied to the
+	/// walk; false when ignore files were disabled or none were found.
+	pub gitignore_applied: bool,
 }
 
-fn evict_oldest() {
-	let max = max_cache_entries();
-	if FS_CACHE.len() > max
-		&& let Some(oldest_key) = FS_CACHE
+/// Enforces the `max` entry cap within `namespace` of `cache`, leaving other
+/// namespaces alone, and returns the evicted entry.
+///
+/// Evicts the oldest entry not in `pinned`; the oldest pinned one goes only
+/// when every entry in the namespace is pinned.
+fn evict_oldest(
+	cache: &DashMap<CacheKey, CacheEntry>,
+	pinned: &DashSet<CacheKey>,
+	namespace: &str,
+	max: usize,
+) -> Option<(CacheKey, CacheEntry)> {
+	let in_namespace = || {
+		cache
 			.iter()
-			.min_by_key(|entry| entry.value().created_at)
-			.map(|entry| entry.key().clone())
+			.filter(|entry| entry.key().namespace == namespace)
+	};
+	if in_namespace().count() <= max {
+		return None;
+	}
+	let oldest_key = in_namespace()
+		.min_by_key(|entry| (pinned.contains(entry.key()), entry.value().created_at))
+		.map(|entry| entry.key().clone())?;
+	cache.remove(&oldest_key)
+}
+
+/// Enforces the global byte ceiling, evicting the oldest entries regardless of
+/// namespace. Runs after [`evict_oldest`], so namespace caps apply first.
+fn evict_over_budget() {
+	let entries = FS_CACHE
+		.iter()
+		.map(|entry| (entry.key().clone(), entry.value().created_at, entry.value().bytes))
+		.collect();
+	for key in keys_over_budget(entries, max_cache_bytes()) {
+		notify_evicted(FS_CACHE.remove(&key), "byteBudget");
+	}
+}
+
+/// Listener registered through `onFsScanCacheEvict`.
+static EVICT_LISTENER: Mutex<Option<ThreadsafeFunction<FsScanCacheEviction>>> = Mutex::new(None);
+
+/// Reports an entry removed by eviction to the `onFsScanCacheEvict` listener.
+fn notify_evicted(removed: Option<(CacheKey, CacheEntry)>, reason: &str) {
+	let listener = EVICT_LISTENER.lock();
+	if let Some(listener) = listener.as_ref()
+		&& let Some((key, entry)) = removed
 	{
-		FS_CACHE.remove(&oldest_key);
+		let eviction = FsScanCacheEviction {
+			root:        key.root.to_string_lossy().into_owned(),
+			age_ms:      entry.created_at.elapsed().as_millis().min(u32::MAX as u128) as u32,
+			entry_count: entry.entries.len().min(u32::MAX as usize) as u32,
+			reason:      reason.to_string(),
+		};
+		listener.call(Ok(eviction), ThreadsafeFunctionCallMode::NonBlocking);
 	}
 }
 
+/// Keys to evict, oldest first, until the remaining entries fit in
+/// `max_bytes`.
+fn keys_over_budget(
+	mut entries: Vec<(CacheKey, Instant, usize)>,
+	max_bytes: usize,
+) -> Vec<CacheKey> {
+	let mut total: usize = entries.iter().map(|(_, _, bytes)| bytes).sum();
+	entries.sort_by_key(|(_, created_at, _)| *created_at);
+	entries
+		.into_iter()
+		.take_while(|(_, _, bytes)| {
+			if total <= max_bytes {
+				return false;
+			}
+			total -= bytes;
+			true
+		})
+		.map(|(key, ..)| key)
+		.collect()
+}
+
 // ═══════════════════════════════════════════════════════════════════════════
 // Path utilities
 // ═══════════════════════════════════════════════════════════════════════════
 
+/// Resolve a path string against the current working directory.
+pub fn absolute_path(path: &str) -> Result<PathBuf> {
+	let candidate = PathBuf::from(path);
+	if candidate.is_absolute() {
+		return Ok(candidate);
+	}
+	let cwd = std::env::current_dir()
+		.map_err(|err| error::coded(ErrorCode::Io, format!("Failed to resolve cwd: {err}")))?;
+	Ok(cwd.join(candidate))
+}
+
 /// Resolve a search path string to a canonical `PathBuf` (must be a directory).
 pub fn resolve_search_path(path: &str) -> Result<PathBuf> {
-	let candidate = PathBuf::from(path);
-	let root = if candidate.is_absolute() {
-		candidate
-	} else {
-		let cwd = std::env::current_dir()
-			.map_err(|err| Error::from_reason(format!("Failed to resolve cwd: {err}")))?;
-		cwd.join(candidate)
-	};
+	let root = absolute_path(path)?;
 	let metadata = std::fs::metadata(&root)
-		.map_err(|err| Error::from_reason(format!("Path not found: {err}")))?;
+		.map_err(|err| error::coded(ErrorCode::PathNotFound, format!("Path not found: {err}")))?;
 	if !metadata.is_dir() {
-		return Err(Error::from_reason("Search path must be a directory".to_string()));
+		return Err(error::coded(ErrorCode::NotADirectory, "Search path must be a directory"));
 	}
 	Ok(std::fs::canonicalize(&root).unwrap_or(root))
 }
@@ -207,16 +516,51 @@ pub fn classify_file_type(path: &Path) -> Option<(FileType, Option<f64>)> {
 // Walker + collection
 // ═══════════════════════════════════════════════════════════════════════════
 
+/// Project ignore file for agent tooling, in `.gitignore` syntax. Git doesn't
+/// read it, and it applies outside git repositories too.
+pub const PIIGNORE_FILENAME: &str = ".piignore";
+
 /// Builds a deterministic filesystem walker configured for visibility and
 /// ignore rules.
-pub fn build_walker(root: &Path, include_hidden: bool, use_gitignore: bool) -> WalkBuilder {
+///
+/// With [`ScanOptions::max_symlink_depth`], entries below a directory reached
+/// through more followed symlinks than allowed are pruned.
+pub fn build_walker(root: &Path, options: ScanOptions) -> WalkBuilder {
 	let mut builder = WalkBuilder::new(root);
 	builder
-		.hidden(!include_hidden)
-		.follow_links(false)
+		.hidden(!options.include_hidden)
+		.follow_links(options.follow_links)
+		.same_file_system(options.same_file_system)
 		.sort_by_file_path(|a, b| a.cmp(b));
 
-	if use_gitignore {
+	if options.follow_links
+		&& let Some(max_depth) = options.max_symlink_depth
+	{
+		// Symlinks crossed to reach each directory; unrecorded paths crossed
+		// none. Parents are always visited before their children.
+		let hops = DashMap::<PathBuf, u32>::new();
+		builder.filter_entry(move |entry| {
+			let parent_hops = entry
+				.path()
+				.parent()
+				.and_then(|parent| hops.get(parent).map(|count| *count))
+				.unwrap_or(0);
+			if parent_hops > max_depth {
+				return false;
+			}
+			let own_hops = parent_hops + u32::from(entry.depth() > 0 && entry.path_is_symlink());
+			if own_hops > 0
+				&& entry
+					.file_type()
+					.is_some_and(|file_type| file_type.is_dir())
+			{
+				hops.insert(entry.path().to_path_buf(), own_hops);
+			}
+			true
+		});
+	}
+
+	if options.use_gitignore {
 		// Honor repository and global ignore files for repo-like behavior.
 		builder
 			.git_ignore(true)
@@ -234,47 +578,527 @@ pub fn build_walker(root: &Path, include_hidden: bool, use_gitignore: bool) -> W
 			.parents(false);
 	}
 
+	if options.use_piignore {
+		// Layers on top of the sources above, taking precedence over them.
+		builder.add_custom_ignore_filename(PIIGNORE_FILENAME);
+	}
+
 	builder
 }
 
+/// Identity of the physical file behind `path`, following symlinks.
+#[cfg(unix)]
+fn physical_identity(path: &Path) -> Option<(u64, u64)> {
+	use std::os::unix::fs::MetadataExt;
+
+	let metadata = std::fs::metadata(path).ok()?;
+	Some((metadata.dev(), metadata.ino()))
+}
+
+/// Identity of the physical file behind `path`, following symlinks.
+#[cfg(not(unix))]
+fn physical_identity(path: &Path) -> Option<PathBuf> {
+	std::fs::canonicalize(path).ok()
+}
+
+/// Device holding the file behind `path`, following symlinks.
+#[cfg(unix)]
+fn device_id(path: &Path) -> Option<u64> {
+	use std::os::unix::fs::MetadataExt;
+
+	std::fs::metadata(path).ok().map(|metadata| metadata.dev())
+}
+
+/// Device holding the file behind `path`; unknown off Unix, where every path
+/// compares as the same device.
+#[cfg(not(unix))]
+const fn device_id(_path: &Path) -> Option<u64> {
+	None
+}
+
+/// Symlinks crossed walking from `root` down to `path`, counting `path`.
+fn symlink_hops(root: &Path, path: &Path) -> u32 {
+	let mut current = root.to_path_buf();
+	let mut hops = 0;
+	for component in path.strip_prefix(root).unwrap_or(path).components() {
+		current.push(component);
+		hops += u32::from(current.is_symlink());
+	}
+	hops
+}
+
+/// Cache record for a walked path, or `None` when the path is skipped (`.git`,
+/// the root itself, or unreadable metadata).
+///
+/// Keeps `node_modules` entries; the cache always stores them and caller-side
+/// filtering is applied later. With `walk_start`, the entry is stamped with the
+/// time since then.
+fn scanned_entry(root: &Path, path: &Path, walk_start: Option<Instant>) -> Option<GlobMatch> {
+	if should_skip_path(path, true) {
+		return None;
+	}
+
+	let relative = normalize_relative_path(root, path);
+	if relative.is_empty() {
+		// Ignore the synthetic root entry ("" relative path).
+		return None;
+	}
+
+	let (file_type, mtime) = classify_file_type(path)?;
+	let mut entry = GlobMatch::new(relative.into_owned(), file_type, mtime);
+	entry.discovered_at_ms = walk_start.map(|start| start.elapsed().as_secs_f64() * 1000.0);
+	Some(entry)
+}
+
+/// Called with the number of entries a walk has discovered so far, every
+/// [`SCAN_PROGRESS_INTERVAL`] entries; may run on walker threads.
+pub type ScanProgress<'a> = &'a (dyn Fn(usize) + Sync);
+
+const SCAN_PROGRESS_INTERVAL: usize = 1000;
+
 /// Scans filesystem entries and records normalized relative paths with file
 /// metadata.
 ///
 /// Always stores `node_modules` entries; caller-side filtering handles
-/// exclusion.
+/// exclusion. Stops early once [`ScanOptions::max_entries`] is reached.
+/// Dispatches to [`collect_entries_parallel`] when [`ScanOptions::threads`]
+/// is set.
 fn collect_entries(
 	root: &Path,
-	include_hidden: bool,
-	use_gitignore: bool,
+	options: ScanOptions,
+	progress: Option<ScanProgress<'_>>,
 	ct: &task::CancelToken,
-) -> Result<Vec<GlobMatch>> {
-	let builder = build_walker(root, include_hidden, use_gitignore);
+) -> Result<ScanResult> {
+	#[cfg(test)]
+	{
+		let delay = SCAN_TEST_DELAY.get();
+		if !delay.is_zero() {
+			std::thread::sleep(delay);
+			ct.heartbeat()?;
+		}
+	}
+	// The walker only honors `.gitignore` inside a git repository.
+	let git_root = if options.use_gitignore {
+		find_git_root(root)
+	} else {
+		None
+	};
+	let mut gitignore_applied =
+		git_root.is_some_and(|git_root| repo_ignore_rules_exist(root, git_root));
+
+	if let Some(threads) = options.threads {
+		return collect_entries_parallel(
+			root,
+			options,
+			threads,
+			git_root,
+			gitignore_applied,
+			progress,
+			ct,
+		);
+	}
+
+	let walk_start = options.record_discovery.then(Instant::now);
+	let builder = build_walker(root, options);
 	let mut entries = Vec::new();
+	let mut seen = HashSet::new();
+	let mut capped = false;
+	let mut symlink_loops = 0u32;
 
 	for entry in builder.build() {
 		ct.heartbeat()?;
 
-		let Ok(entry) = entry else { continue };
+		let entry = match entry {
+			Ok(entry) => entry,
+			Err(err) => {
+				if is_symlink_loop(&err) {
+					symlink_loops = symlink_loops.saturating_add(1);
+				}
+				continue;
+			},
+		};
 		let path = entry.path();
-		if should_skip_path(path, true) {
-			// The cache always stores node_modules; caller-side filtering is applied later.
+		let Some(scanned) = scanned_entry(root, path, walk_start) else {
 			continue;
+		};
+		if !gitignore_applied && git_root.is_some() && scanned.file_type == FileType::Dir {
+			gitignore_applied = has_ignore_rules(&path.join(".gitignore"));
 		}
 
-		let relative = normalize_relative_path(root, path);
-		if relative.is_empty() {
-			// Ignore the synthetic root entry ("" relative path).
+		if options.dedup_by_inode
+			&& let Some(identity) = physical_identity(path)
+			&& !seen.insert(identity)
+		{
+			// Same physical file already recorded under another path.
 			continue;
 		}
 
-		let Some((file_type, mtime)) = classify_file_type(path) else {
+		if options.max_entries.is_some_and(|max| entries.len() >= max) {
+			capped = true;
+			break;
+		}
+		entries.push(scanned);
+		if let Some(progress) = progress
+			&& entries.len() % SCAN_PROGRESS_INTERVAL == 0
+		{
+			progress(entries.len());
+		}
+	}
+
+	Ok(ScanResult {
+		entries,
+		cache_age_ms: 0,
+		capped,
+		from_cache: false,
+		symlink_loops,
+		gitignore_applied,
+	})
+}
+
+/// [`collect_entries`] on the parallel walker with `threads` workers.
+///
+/// Entries are sorted by path afterwards so the result matches the sequential
+/// walk, and inode dedup keeps the first path in that order. A capped scan
+/// stops once `max_entries` entries are collected, so which entries survive
+/// the cap depends on thread scheduling.
+fn collect_entries_parallel(
+	root: &Path,
+	options: ScanOptions,
+	threads: usize,
+	git_root: Option<&Path>,
+	gitignore_applied: bool,
+	progress: Option<ScanProgress<'_>>,
+	ct: &task::CancelToken,
+) -> Result<ScanResult> {
+	let limit = options.max_entries.unwrap_or(usize::MAX);
+	let found = Mutex::new(Vec::new());
+	let failure = Mutex::new(None);
+	let capped = AtomicBool::new(false);
+	let symlink_loops = AtomicU32::new(0);
+	let gitignore_applied = AtomicBool::new(gitignore_applied);
+
+	let walk_start = options.record_discovery.then(Instant::now);
+	let mut builder = build_walker(root, options);
+	builder.threads(threads).build_parallel().run(|| {
+		let (found, failure, capped, symlink_loops, gitignore_applied) =
+			(&found, &failure, &capped, &symlink_loops, &gitignore_applied);
+		Box::new(move |entry| {
+			if let Err(err) = ct.heartbeat() {
+				*failure.lock() = Some(err);
+				return WalkState::Quit;
+			}
+
+			let entry = match entry {
+				Ok(entry) => entry,
+				Err(err) => {
+					if is_symlink_loop(&err) {
+						symlink_loops.fetch_add(1, Ordering::Relaxed);
+					}
+					return WalkState::Continue;
+				},
+			};
+			let path = entry.path();
+			let Some(scanned) = scanned_entry(root, path, walk_start) else {
+				return WalkState::Continue;
+			};
+			if git_root.is_some()
+				&& scanned.file_type == FileType::Dir
+				&& !gitignore_applied.load(Ordering::Relaxed)
+				&& has_ignore_rules(&path.join(".gitignore"))
+			{
+				gitignore_applied.store(true, Ordering::Relaxed);
+			}
+			let identity = if options.dedup_by_inode {
+				physical_identity(path)
+			} else {
+				None
+			};
+
+			let mut found = found.lock();
+			if found.len() >= limit {
+				capped.store(true, Ordering::Relaxed);
+				return WalkState::Quit;
+			}
+			found.push((scanned, identity));
+			let discovered = found.len();
+			drop(found);
+			if let Some(progress) = progress
+				&& discovered % SCAN_PROGRESS_INTERVAL == 0
+			{
+				progress(discovered);
+			}
+			WalkState::Continue
+		})
+	});
+
+	if let Some(err) = failure.into_inner() {
+		return Err(err);
+	}
+
+	let mut found = found.into_inner();
+	// Depth-first order with sorted siblings is component-wise path order.
+	found.sort_unstable_by(|(a, _), (b, _)| Path::new(&a.path).cmp(Path::new(&b.path)));
+	let mut seen = HashSet::new();
+	let entries = found
+		.into_iter()
+		.filter_map(|(entry, identity)| match identity {
+			// Same physical file already recorded under an earlier path.
+			Some(identity) if !seen.insert(identity) => None,
+			_ => Some(entry),
+		})
+		.collect();
+
+	Ok(ScanResult {
+		entries,
+		cache_age_ms: 0,
+		capped: capped.into_inner(),
+		from_cache: false,
+		symlink_loops: symlink_loops.into_inner(),
+		gitignore_applied: gitignore_applied.into_inner(),
+	})
+}
+
+/// Whether the ignore rules [`build_walker`] honors with `use_gitignore`
+/// exclude `path`.
+///
+/// Inside a git repository, `.gitignore` files from the repository root down
+/// and `.git/info/exclude` apply; `.ignore` files apply everywhere. Deeper
+/// files take precedence, and `.ignore` beats `.gitignore` in the same
+/// directory. [`PIIGNORE_FILENAME`] files, which the walker honors by
+/// default, apply everywhere and take precedence over all of these. The
+/// global git excludes file is not consulted.
+pub fn is_path_ignored(root: &Path, path: &Path) -> bool {
+	if path
+		.strip_prefix(root)
+		.is_ok_and(|relative| relative.components().any(|part| part.as_os_str() == ".git"))
+	{
+		// The walker never descends into `.git`.
+		return true;
+	}
+	let git_root = find_git_root(root);
+	let is_dir = path.is_dir();
+	let mut matchers: Vec<Gitignore> = path
+		.ancestors()
+		.skip(1)
+		.map(|dir| Gitignore::new(dir.join(PIIGNORE_FILENAME)).0)
+		.collect();
+	for dir in path.ancestors().skip(1) {
+		matchers.push(Gitignore::new(dir.join(".ignore")).0);
+		if git_root.is_some_and(|git_root| dir.starts_with(git_root)) {
+			matchers.push(Gitignore::new(dir.join(".gitignore")).0);
+		}
+	}
+	if let Some(git_root) = git_root.filter(|git_root| path.starts_with(git_root)) {
+		let mut builder = GitignoreBuilder::new(git_root);
+		builder.add(git_root.join(".git/info/exclude"));
+		if let Ok(exclude) = builder.build() {
+			matchers.push(exclude);
+		}
+	}
+	matchers
+		.iter()
+		.map(|matcher| matcher.matched_path_or_any_parents(path, is_dir))
+		.find(|matched| !matched.is_none())
+		.is_some_and(|matched| matched.is_ignore())
+}
+
+/// Nearest directory at or above `root` containing `.git` (a directory, or a
+/// file for worktrees and submodules).
+fn find_git_root(root: &Path) -> Option<&Path> {
+	root.ancestors().find(|dir| dir.join(".git").exists())
+}
+
+/// Whether ignore rules from outside the walk apply to `root`: `.gitignore`
+/// files in `root` or its ancestors up to `git_root`, or `.git/info/exclude`.
+fn repo_ignore_rules_exist(root: &Path, git_root: &Path) -> bool {
+	root
+		.ancestors()
+		.take_while(|dir| dir.starts_with(git_root))
+		.any(|dir| has_ignore_rules(&dir.join(".gitignore")))
+		|| has_ignore_rules(&git_root.join(".git/info/exclude"))
+}
+
+/// Whether an ignore file exists and holds at least one pattern (git's
+/// default `info/exclude` is only comments).
+fn has_ignore_rules(path: &Path) -> bool {
+	std::fs::read_to_string(path).is_ok_and(|text| {
+		text
+			.lines()
+			.map(str::trim)
+			.any(|line| !line.is_empty() && !line.starts_with('#'))
+	})
+}
+
+/// Whether a walker error reports a symlink cycle, possibly wrapped with
+/// path/depth context.
+fn is_symlink_loop(err: &ignore::Error) -> bool {
+	match err {
+		ignore::Error::Loop { .. } => true,
+		ignore::Error::WithPath { err, .. }
+		| ignore::Error::WithDepth { err, .. }
+		| ignore::Error::WithLineNumber { err, .. } => is_symlink_loop(err),
+		ignore::Error::Partial(errs) => errs.iter().any(is_symlink_loop),
+		_ => false,
+	}
+}
+
+/// Matcher for `reinclude` lines read as `.gitignore` patterns anchored at
+/// `root`; the leading `!` of a negation is optional.
+fn reinclude_matcher(root: &Path, reinclude: &[String]) -> Result<Gitignore> {
+	let invalid = |line: &str, err: ignore::Error| {
+		error::coded(ErrorCode::InvalidPattern, format!("Invalid reinclude pattern {line:?}: {err}"))
+	};
+	let mut builder = GitignoreBuilder::new(root);
+	for line in reinclude {
+		let pattern = line.trim_start();
+		builder
+			.add_line(None, pattern.strip_prefix('!').unwrap_or(pattern))
+			.map_err(|err| invalid(line, err))?;
+	}
+	builder.build().map_err(|err| invalid("", err))
+}
+
+/// Adds the entries ignore rules kept out of `scan` that `reinclude` matches,
+/// as if its lines were appended to `.gitignore` as negations.
+///
+/// Only the unlisted children of scanned directories are matched, so this
+/// costs one directory listing per scanned directory rather than a second
+/// walk, and an ignored directory that isn't re-included hides everything
+/// below it, as in git. Re-included directories are walked with ignore rules
+/// off but the scan's other limits on. Capped scans are left as they are.
+fn add_reincluded(
+	root: &Path,
+	options: ScanOptions,
+	reinclude: &[String],
+	scan: &mut ScanResult,
+	ct: &task::CancelToken,
+) -> Result<()> {
+	if reinclude.is_empty() || !(options.use_gitignore || options.use_piignore) || scan.capped {
+		return Ok(());
+	}
+	let matcher = reinclude_matcher(root, reinclude)?;
+
+	let listed: HashSet<&str> = scan
+		.entries
+		.iter()
+		.map(|entry| entry.path.as_str())
+		.collect();
+	let dirs = std::iter::once("").chain(
+		scan
+			.entries
+			.iter()
+			.filter(|entry| match entry.file_type {
+				FileType::Dir => true,
+				// Followed links to directories were walked into too.
+				FileType::Symlink => options.follow_links && root.join(&entry.path).is_dir(),
+				FileType::File => false,
+			})
+			.map(|entry| entry.path.as_str()),
+	);
+	let mut reincluded = Vec::new();
+	for dir in dirs {
+		ct.heartbeat()?;
+		let Ok(children) = std::fs::read_dir(root.join(dir)) else {
 			continue;
 		};
+		for child in children.flatten() {
+			let path = child.path();
+			let relative = normalize_relative_path(root, &path);
+			let hidden = child.file_name().as_encoded_bytes().starts_with(b".");
+			if listed.contains(relative.as_ref())
+				|| (hidden && !options.include_hidden)
+				|| should_skip_path(&path, true)
+			{
+				continue;
+			}
+			let is_dir = child.file_type().is_ok_and(|file_type| file_type.is_dir());
+			if matcher.matched(relative.as_ref(), is_dir).is_ignore() {
+				reincluded.push(path);
+			}
+		}
+	}
+	drop(listed);
+	if reincluded.is_empty() {
+		return Ok(());
+	}
 
-		entries.push(GlobMatch { path: relative.into_owned(), file_type, mtime });
+	let unignored = ScanOptions {
+		use_gitignore: false,
+		use_piignore: false,
+		max_entries: None,
+		threads: None,
+		..options
+	};
+	// Hold the added entries to the walk's inode dedup, symlink depth, and
+	// filesystem limits, which the walks below only apply from `path` down.
+	let mut seen = HashSet::new();
+	if options.dedup_by_inode {
+		seen.extend(
+			scan
+				.entries
+				.iter()
+				.filter_map(|entry| physical_identity(&root.join(&entry.path))),
+		);
+	}
+	let root_device = device_id(root);
+	for path in reincluded {
+		if options.same_file_system && device_id(&path) != root_device {
+			continue;
+		}
+		let mut walk_options = unignored;
+		let mut max_depth = None;
+		if options.follow_links
+			&& let Some(max_hops) = options.max_symlink_depth
+		{
+			let hops = symlink_hops(root, &path);
+			if hops - u32::from(path.is_symlink()) > max_hops {
+				// Its parent was reached through too many symlinks.
+				continue;
+			}
+			if hops > max_hops {
+				max_depth = Some(0);
+			}
+			walk_options.max_symlink_depth = Some(max_hops.saturating_sub(hops));
+		}
+		let mut walker = build_walker(&path, walk_options);
+		walker.max_depth(max_depth);
+		for walked in walker.build() {
+			ct.heartbeat()?;
+			let Ok(walked) = walked else { continue };
+			if options.dedup_by_inode
+				&& let Some(identity) = physical_identity(walked.path())
+				&& !seen.insert(identity)
+			{
+				continue;
+			}
+			scan
+				.entries
+				.extend(scanned_entry(root, walked.path(), None));
+		}
+	}
+	scan
+		.entries
+		.sort_by(|a, b| Path::new(&a.path).cmp(Path::new(&b.path)));
+	if let Some(max) = options.max_entries
+		&& scan.entries.len() > max
+	{
+		scan.entries.truncate(max);
+		scan.capped = true;
 	}
+	Ok(())
+}
 
-	Ok(entries)
+/// [`collect_entries`] extended with the entries `reinclude` adds back.
+fn scan_root(
+	root: &Path,
+	options: ScanOptions,
+	reinclude: &[String],
+	progress: Option<ScanProgress<'_>>,
+	ct: &task::CancelToken,
+) -> Result<ScanResult> {
+	let mut scan = collect_entries(root, options, progress, ct)?;
+	add_reincluded(root, options, reinclude, &mut scan, ct)?;
+	Ok(scan)
 }
 
 // ═══════════════════════════════════════════════════════════════════════════
@@ -286,63 +1110,78 @@ fn collect_entries(
 /// The returned [`ScanResult::cache_age_ms`] lets callers implement
 /// empty-result fast recheck: if a query produces zero matches and the cache is
 /// older than [`empty_recheck_ms()`], call [`force_rescan`] before returning
-/// empty.
+/// empty. Cached scans larger than [`ScanOptions::max_entries`] are truncated
+/// and reported as capped. Entries are cached under `namespace`, which has its
+/// own entry cap. `progress` only hears from fresh walks, not cache hits.
+///
+/// Non-empty `reinclude` lines add back ignored entries they match, as
+/// gitignore negations would; the lines are part of the cache key.
 pub fn get_or_scan(
 	root: &Path,
-	include_hidden: bool,
-	use_gitignore: bool,
+	options: ScanOptions,
+	reinclude: &[String],
+	namespace: &str,
+	progress: Option<ScanProgress<'_>>,
 	ct: &task::CancelToken,
 ) -> Result<ScanResult> {
 	let ttl = cache_ttl_ms();
 	if ttl == 0 {
 		// Caching disabled – always scan fresh.
-		let entries = collect_entries(root, include_hidden, use_gitignore, ct)?;
-		return Ok(ScanResult { entries, cache_age_ms: 0 });
+		return scan_root(root, options, reinclude, progress, ct);
 	}
 
-	let key = CacheKey { root: root.to_path_buf(), include_hidden, use_gitignore };
+	let key = CacheKey { reinclude: reinclude.to_vec(), ..CacheKey::new(root, options, namespace) };
 
 	let now = Instant::now();
 	if let Some(entry) = FS_CACHE.get(&key) {
 		let age = now.duration_since(entry.created_at);
 		if age < Duration::from_millis(ttl) {
+			let limit = options.max_entries.unwrap_or(usize::MAX);
 			return Ok(ScanResult {
-				entries:      entry.entries.clone(),
-				cache_age_ms: age.as_millis() as u64,
+				entries:           entry.entries.iter().take(limit).cloned().collect(),
+				cache_age_ms:      age.as_millis() as u64,
+				capped:            entry.entries.len() > limit,
+				from_cache:        true,
+				symlink_loops:     entry.symlink_loops,
+				gitignore_applied: entry.gitignore_applied,
 			});
 		}
 		drop(entry);
 		FS_CACHE.remove(&key);
 	}
 
-	let entries = collect_entries(root, include_hidden, use_gitignore, ct)?;
-	FS_CACHE.insert(key, CacheEntry { created_at: now, entries: entries.clone() });
-	evict_oldest();
-	Ok(ScanResult { entries, cache_age_ms: 0 })
+	let epoch = root_epoch(root);
+	let scan = scan_root(root, options, reinclude, progress, ct)?;
+	if !scan.capped {
+		store_scan(key, epoch, now, &scan);
+	}
+	Ok(scan)
 }
 
 /// Force a fresh scan, replacing any existing cache entry.
 ///
 /// Use when a cached query produced zero matches and the cache was old enough
-/// to warrant a recheck. When `store` is false, the fresh scan result is
-/// returned without repopulating the cache.
+/// to warrant a recheck. When `store` is false (or the scan was capped), the
+/// fresh scan result is returned without repopulating the cache. `reinclude`
+/// is as for [`get_or_scan`].
 pub fn force_rescan(
 	root: &Path,
-	include_hidden: bool,
-	use_gitignore: bool,
+	options: ScanOptions,
+	reinclude: &[String],
+	namespace: &str,
 	store: bool,
+	progress: Option<ScanProgress<'_>>,
 	ct: &task::CancelToken,
-) -> Result<Vec<GlobMatch>> {
-	let key = CacheKey { root: root.to_path_buf(), include_hidden, use_gitignore };
+) -> Result<ScanResult> {
+	let key = CacheKey { reinclude: reinclude.to_vec(), ..CacheKey::new(root, options, namespace) };
 	FS_CACHE.remove(&key);
 
-	let entries = collect_entries(root, include_hidden, use_gitignore, ct)?;
-	if store {
-		let now = Instant::now();
-		FS_CACHE.insert(key, CacheEntry { created_at: now, entries: entries.clone() });
-		evict_oldest();
+	let epoch = root_epoch(root);
+	let scan = scan_root(root, options, reinclude, progress, ct)?;
+	if store && !scan.capped {
+		store_scan(key, epoch, Instant::now(), &scan);
 	}
-	Ok(entries)
+	Ok(scan)
 }
 
 // ═══════════════════════════════════════════════════════════════════════════
@@ -352,8 +1191,10 @@ pub fn force_rescan(
 /// Invalidate cache entries whose root contains `target`.
 ///
 /// Removes any cache entry whose root is a prefix of (or equal to) `target`,
-/// because a file mutation under that root makes the scan stale.
+/// because a file mutation under that root makes the scan stale. Scans of those
+/// roots already in flight will not be cached.
 pub fn invalidate_path(target: &Path) {
+	advance_epochs(|root| target.starts_with(root));
 	let keys_to_remove: Vec<CacheKey> = FS_CACHE
 		.iter()
 		.filter(|entry| target.starts_with(&entry.key().root))
@@ -364,11 +1205,133 @@ pub fn invalidate_path(target: &Path) {
 	}
 }
 
+/// Where `root` ends up after `from` was renamed to `to`, if it moved.
+fn moved_root(root: &Path, from: &Path, to: &Path) -> Option<PathBuf> {
+	let suffix = root.strip_prefix(from).ok()?;
+	Some(if suffix.as_os_str().is_empty() {
+		to.to_path_buf()
+	} else {
+		to.join(suffix)
+	})
+}
+
+/// Relocate cache entries after `from` was renamed to `to`.
+///
+/// - Roots at or under `from` are re-keyed, pins included; their relative
+///   entries are kept.
+/// - Roots containing both paths get entry paths under `from` rewritten.
+/// - Roots that only gain new content (or gain hidden content they exclude) are
+///   invalidated.
+///
+/// Ignore rules are not re-evaluated at the destination.
+pub fn rename_path(from: &Path, to: &Path) {
+	advance_epochs(|root| from.starts_with(root) || to.starts_with(root) || root.starts_with(from));
+	let moved_pins: Vec<(CacheKey, PathBuf)> = PINNED_KEYS
+		.iter()
+		.filter_map(|key| moved_root(&key.root, from, to).map(|root| (key.clone(), root)))
+		.collect();
+	for (key, root) in moved_pins {
+		PINNED_KEYS.remove(&key);
+		PINNED_KEYS.insert(CacheKey { root, ..key });
+	}
+
+	let keys: Vec<CacheKey> = FS_CACHE.iter().map(|entry| entry.key().clone()).collect();
+	for key in keys {
+		if let Some(root) = moved_root(&key.root, from, to) {
+			// The whole root moved; relative entry paths are unchanged.
+			if let Some((_, entry)) = FS_CACHE.remove(&key) {
+				FS_CACHE.insert(CacheKey { root, ..key }, entry);
+			}
+			continue;
+		}
+		if !from.starts_with(&key.root) {
+			if to.starts_with(&key.root) {
+				// Content moved in from outside this root; we never scanned it.
+				FS_CACHE.remove(&key);
+			}
+			continue;
+		}
+
+		let from_rel = normalize_relative_path(&key.root, from).into_owned();
+		let to_rel = to
+			.starts_with(&key.root)
+			.then(|| normalize_relative_path(&key.root, to).into_owned());
+		if let Some(to_rel) = &to_rel
+			&& !key.options.include_hidden
+			&& to_rel
+				.split('/')
+				.any(|component| component.starts_with('.'))
+		{
+			FS_CACHE.remove(&key);
+			continue;
+		}
+
+		let Some(mut entry) = FS_CACHE.get_mut(&key) else {
+			continue;
+		};
+		let moved_prefix = format!("{from_rel}/");
+		entry.entries.retain_mut(|item| {
+			let rest = if item.path == from_rel {
+				""
+			} else if let Some(rest) = item.path.strip_prefix(&moved_prefix) {
+				rest
+			} else {
+				return true;
+			};
+			// Entries moved out of this root are dropped.
+			let Some(to_rel) = &to_rel else {
+				return false;
+			};
+			item.path = if rest.is_empty() {
+				to_rel.clone()
+			} else {
+				format!("{to_rel}/{rest}")
+			};
+			true
+		});
+		// Restore walker order (depth-first, siblings sorted by name).
+		entry
+			.entries
+			.sort_by(|a, b| Path::new(&a.path).cmp(Path::new(&b.path)));
+		entry.bytes = entries_bytes(&entry.entries);
+	}
+}
+
 /// Clear the entire scan cache.
 pub fn invalidate_all() {
+	advance_epochs(|_| true);
 	FS_CACHE.clear();
 }
 
+/// Resolve a mutated path to the canonical form used for cache roots.
+///
+/// Falls back to canonicalizing the parent when the path itself no longer
+/// exists (e.g. after a delete or rename).
+fn resolve_mutation_path(path: &str) -> PathBuf {
+	let candidate = PathBuf::from(path);
+	let absolute = if candidate.is_absolute() {
+		candidate
+	} else if let Ok(cwd) = std::env::current_dir() {
+		cwd.join(candidate)
+	} else {
+		PathBuf::from(path)
+	};
+	canonicalize_lossy(absolute)
+}
+
+/// Canonicalize `absolute`, or just its parent when it doesn't exist.
+fn canonicalize_lossy(absolute: PathBuf) -> PathBuf {
+	std::fs::canonicalize(&absolute)
+		.or_else(|_| {
+			absolute
+				.parent()
+				.and_then(|parent| std::fs::canonicalize(parent).ok())
+				.and_then(|parent| absolute.file_name().map(|name| parent.join(name)))
+				.ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound))
+		})
+		.unwrap_or(absolute)
+}
+
 /// Invalidate the filesystem scan cache.
 ///
 /// When called with a path, removes entries for roots containing that path.
@@ -379,26 +1342,625 @@ pub fn invalidate_all() {
 #[napi(js_name = "invalidateFsScanCache")]
 pub fn invalidate_fs_scan_cache(path: Option<String>) {
 	match path {
-		Some(p) => {
-			let candidate = PathBuf::from(&p);
-			let absolute = if candidate.is_absolute() {
-				candidate
-			} else if let Ok(cwd) = std::env::current_dir() {
-				cwd.join(candidate)
-			} else {
-				PathBuf::from(&p)
-			};
-			let target = std::fs::canonicalize(&absolute)
-				.or_else(|_| {
-					absolute
-						.parent()
-						.and_then(|parent| std::fs::canonicalize(parent).ok())
-						.and_then(|parent| absolute.file_name().map(|name| parent.join(name)))
-						.ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound))
-				})
-				.unwrap_or(absolute);
-			invalidate_path(&target);
-		},
+		Some(p) => invalidate_path(&resolve_mutation_path(&p)),
 		None => invalidate_all(),
 	}
 }
+
+/// Update the filesystem scan cache after renaming `from` to `to`.
+///
+/// Cached scans stay warm instead of being dropped: roots under `from` are
+/// re-keyed, and entries of roots containing both paths are rewritten.
+/// Only valid for pure relocations with unchanged contents.
+#[napi(js_name = "renameFsScanCache")]
+pub fn rename_fs_scan_cache(from: String, to: String) {
+	rename_path(&resolve_mutation_path(&from), &resolve_mutation_path(&to));
+}
+
+/// Report whether the ignore rules `glob` applies with `gitignore: true` would
+/// exclude `path`, without walking.
+///
+/// `path` may be relative to `root` or absolute, and need not exist.
+///
+/// # Errors
+/// Returns an error when `root` cannot be resolved or is not a directory.
+#[napi(js_name = "isIgnored")]
+pub fn is_ignored(root: String, path: String) -> Result<bool> {
+	let root = resolve_search_path(&root)?;
+	let path = canonicalize_lossy(root.join(path));
+	Ok(is_path_ignored(&root, &path))
+}
+
+/// Freshness of a cached scan, as reported by `fsScanCacheStatus`.
+#[napi(object)]
+pub struct FsScanCacheStatus {
+	/// Age of the cached scan in milliseconds.
+	pub age_ms:      u32,
+	/// Number of entries in the cached scan.
+	pub entry_count: u32,
+}
+
+/// Report whether a scan of `path` with the given settings is cached.
+///
+/// `hidden`, `gitignore`, and `namespace` default as in `glob` (false / true /
+/// shared). Returns `null` when the path cannot be resolved or no unexpired
+/// scan is cached.
+#[napi(js_name = "fsScanCacheStatus")]
+pub fn fs_scan_cache_status(
+	path: String,
+	hidden: Option<bool>,
+	gitignore: Option<bool>,
+	namespace: Option<String>,
+) -> Option<FsScanCacheStatus> {
+	let root = resolve_search_path(&path).ok()?;
+	let options = ScanOptions::new(hidden.unwrap_or(false), gitignore.unwrap_or(true));
+	let namespace = namespace.as_deref().unwrap_or(SHARED_NAMESPACE);
+	let entry = FS_CACHE.get(&CacheKey::new(&root, options, namespace))?;
+	let age = entry.created_at.elapsed();
+	(age < Duration::from_millis(cache_ttl_ms())).then(|| FsScanCacheStatus {
+		age_ms:      age.as_millis().min(u32::MAX as u128) as u32,
+		entry_count: entry.entries.len().min(u32::MAX as usize) as u32,
+	})
+}
+
+/// Size of the whole scan cache, as reported by `fsScanCacheStats`.
+#[napi(object)]
+pub struct FsScanCacheStats {
+	/// Number of cached scans across all namespaces.
+	pub entry_count:     u32,
+	/// Estimated memory held by all cached scans, in bytes.
+	pub total_bytes:     f64,
+	/// Configured ceiling on `totalBytes` (`FS_SCAN_CACHE_MAX_BYTES`).
+	pub max_total_bytes: f64,
+}
+
+/// Report the combined size of every cached scan, across namespaces.
+#[napi(js_name = "fsScanCacheStats")]
+pub fn fs_scan_cache_stats() -> FsScanCacheStats {
+	let total: usize = FS_CACHE.iter().map(|entry| entry.value().bytes).sum();
+	FsScanCacheStats {
+		entry_count:     FS_CACHE.len().min(u32::MAX as usize) as u32,
+		total_bytes:     total as f64,
+		max_total_bytes: max_cache_bytes() as f64,
+	}
+}
+
+/// Drop every cached scan older than the TTL, across namespaces, and return
+/// how many were removed.
+///
+/// Expired scans otherwise stay in memory until their key is read again or
+/// eviction reaches them; long-lived processes can call this periodically.
+#[napi(js_name = "pruneFsScanCache")]
+pub fn prune_fs_scan_cache() -> u32 {
+	prune_expired(Duration::from_millis(cache_ttl_ms()))
+}
+
+/// Removes cached scans at least `ttl` old, returning how many were removed.
+fn prune_expired(ttl: Duration) -> u32 {
+	let mut pruned = 0u32;
+	FS_CACHE.retain(|_, entry| {
+		let keep = entry.created_at.elapsed() < ttl;
+		pruned += u32::from(!keep);
+		keep
+	});
+	pruned
+}
+
+/// Cache key of a scan of `path` with the settings `fsScanCacheStatus` takes.
+fn settings_key(
+	path: &str,
+	hidden: Option<bool>,
+	gitignore: Option<bool>,
+	namespace: Option<&str>,
+) -> Result<CacheKey> {
+	let root = resolve_search_path(path)?;
+	let options = ScanOptions::new(hidden.unwrap_or(false), gitignore.unwrap_or(true));
+	Ok(CacheKey::new(&root, options, namespace.unwrap_or(SHARED_NAMESPACE)))
+}
+
+/// Keep the scan of `path` with the given settings cached when its namespace
+/// exceeds `FS_SCAN_CACHE_MAX_ENTRIES`, evicting unpinned scans instead.
+///
+/// Defaults match `fsScanCacheStatus`. The pin applies to future scans too and
+/// lasts until `unpinFsScanCache`; the byte ceiling still applies.
+///
+/// # Errors
+/// Returns an error when `path` cannot be resolved or is not a directory.
+#[napi(js_name = "pinFsScanCache")]
+pub fn pin_fs_scan_cache(
+	path: String,
+	hidden: Option<bool>,
+	gitignore: Option<bool>,
+	namespace: Option<String>,
+) -> Result<()> {
+	PINNED_KEYS.insert(settings_key(&path, hidden, gitignore, namespace.as_deref())?);
+	Ok(())
+}
+
+/// Remove a pin added by `pinFsScanCache` with the same arguments.
+///
+/// Returns whether a pin was removed. The scan stays cached until evicted.
+#[napi(js_name = "unpinFsScanCache")]
+pub fn unpin_fs_scan_cache(
+	path: String,
+	hidden: Option<bool>,
+	gitignore: Option<bool>,
+	namespace: Option<String>,
+) -> bool {
+	settings_key(&path, hidden, gitignore, namespace.as_deref())
+		.is_ok_and(|key| PINNED_KEYS.remove(&key).is_some())
+}
+
+/// A scan dropped from the cache to make room, as reported to the
+/// `onFsScanCacheEvict` listener.
+#[napi(object)]
+pub struct FsScanCacheEviction {
+	/// Root directory of the evicted scan.
+	pub root:        String,
+	/// Age of the evicted scan in milliseconds.
+	pub age_ms:      u32,
+	/// Number of entries in the evicted scan.
+	pub entry_count: u32,
+	/// `"entryCap"` (its namespace exceeded `FS_SCAN_CACHE_MAX_ENTRIES`) or
+	/// `"byteBudget"` (the cache exceeded `FS_SCAN_CACHE_MAX_BYTES`).
+	pub reason:      String,
+}
+
+/// Call `callback` with each scan the cache evicts, replacing any previous
+/// listener; `null` removes it.
+///
+/// Invalidation is not eviction and is not reported. The listener does not
+/// keep the process alive.
+#[napi(js_name = "onFsScanCacheEvict")]
+pub fn on_fs_scan_cache_evict(
+	env: &Env,
+	#[napi(ts_arg_type = "((eviction: FsScanCacheEviction) => void) | undefined | null")]
+	callback: Option<ThreadsafeFunction<FsScanCacheEviction>>,
+) -> Result<()> {
+	let callback = callback
+		.map(|mut callback| callback.unref(env).map(|()| callback))
+		.transpose()?;
+	*EVICT_LISTENER.lock() = callback;
+	Ok(())
+}
+
+/// Copy of the cached scan of `path` with the given settings, in walk order.
+///
+/// Defaults and `null` results match `fsScanCacheStatus`. Read-only; for
+/// comparing cached state against the filesystem when debugging staleness.
+#[napi(js_name = "fsScanCacheEntriesForRoot")]
+pub fn fs_scan_cache_entries_for_root(
+	path: String,
+	hidden: Option<bool>,
+	gitignore: Option<bool>,
+	namespace: Option<String>,
+) -> Option<Vec<GlobMatch>> {
+	let root = resolve_search_path(&path).ok()?;
+	let options = ScanOptions::new(hidden.unwrap_or(false), gitignore.unwrap_or(true));
+	let namespace = namespace.as_deref().unwrap_or(SHARED_NAMESPACE);
+	let entry = FS_CACHE.get(&CacheKey::new(&root, options, namespace))?;
+	(entry.created_at.elapsed() < Duration::from_millis(cache_ttl_ms()))
+		.then(|| entry.entries.clone())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+	use super::*;
+
+	/// Scratch directory for one test, removed on drop so it's cleaned up
+	/// even when an assertion fails.
+	pub struct TempDir(PathBuf);
+
+	impl TempDir {
+		/// A fresh, empty directory named after `name` and this process.
+		pub fn new(name: &str) -> Self {
+			let dir = std::env::temp_dir().join(format!("pi-natives-{name}-{}", std::process::id()));
+			let _ = std::fs::remove_dir_all(&dir);
+			std::fs::create_dir_all(&dir).unwrap();
+			Self(dir)
+		}
+	}
+
+	impl std::ops::Deref for TempDir {
+		type Target = Path;
+
+		fn deref(&self) -> &Path {
+			&self.0
+		}
+	}
+
+	impl AsRef<Path> for TempDir {
+		fn as_ref(&self) -> &Path {
+			&self.0
+		}
+	}
+
+	impl Drop for TempDir {
+		fn drop(&mut self) {
+			let _ = std::fs::remove_dir_all(&self.0);
+		}
+	}
+
+	fn cache_entry(created_at: Instant) -> CacheEntry {
+		CacheEntry {
+			created_at,
+			entries: Vec::new(),
+			symlink_loops: 0,
+			gitignore_applied: false,
+			bytes: 0,
+		}
+	}
+
+	#[test]
+	fn test_cached_scan_expires_after_ttl() {
+		let dir = TempDir::new("ttl");
+		std::fs::write(dir.join("a.rs"), "").unwrap();
+		let root = std::fs::canonicalize(&dir).unwrap();
+		let ct = task::CancelToken::default();
+		let options = ScanOptions::new(false, false);
+		let scan = || get_or_scan(&root, options, &[], "test-ttl", None, &ct).unwrap();
+
+		// A walk slower than the TTL caches a scan that has already expired.
+		SCAN_TEST_DELAY.set(Duration::from_millis(cache_ttl_ms() + 50));
+		assert!(!scan().from_cache);
+		SCAN_TEST_DELAY.set(Duration::ZERO);
+		assert!(!scan().from_cache);
+		assert!(scan().from_cache);
+	}
+
+	#[test]
+	fn test_invalidation_during_scan_discards_result() {
+		let root = Path::new("/pi-natives-test/epoch-root");
+		let key = CacheKey::new(root, ScanOptions::new(false, true), SHARED_NAMESPACE);
+
+		let epoch = root_epoch(root);
+		invalidate_path(&root.join("changed.rs"));
+		store_scan(key.clone(), epoch, Instant::now(), &ScanResult::default());
+		assert!(!FS_CACHE.contains_key(&key));
+
+		let epoch = root_epoch(root);
+		store_scan(key.clone(), epoch, Instant::now(), &ScanResult::default());
+		assert!(FS_CACHE.contains_key(&key));
+		invalidate_path(root);
+		assert!(!FS_CACHE.contains_key(&key));
+	}
+
+	#[test]
+	fn test_is_path_ignored() {
+		let dir = TempDir::new("ignored");
+		std::fs::create_dir_all(dir.join(".git")).unwrap();
+		std::fs::create_dir_all(dir.join("src")).unwrap();
+		std::fs::write(dir.join(".gitignore"), "target/\n*.log\n").unwrap();
+		std::fs::write(dir.join("src/.gitignore"), "!keep.log\n").unwrap();
+		std::fs::write(dir.join(PIIGNORE_FILENAME), "gen/\n/src/skip.rs\n").unwrap();
+		let root = std::fs::canonicalize(&dir).unwrap();
+
+		let ignored = |path: &str| is_path_ignored(&root, &root.join(path));
+		assert!(ignored("target/debug/out"));
+		assert!(ignored("a.log"));
+		assert!(!ignored("src/keep.log"));
+		assert!(!ignored("src/main.rs"));
+		assert!(ignored("gen/out.rs"));
+		assert!(ignored("src/skip.rs"));
+		assert!(ignored(".git/HEAD"));
+	}
+
+	#[test]
+	fn test_parallel_scan_matches_sequential_order() {
+		let dir = TempDir::new("parallel");
+		for sub in ["a/b", "a-b", "c", "c/d/e"] {
+			std::fs::create_dir_all(dir.join(sub)).unwrap();
+		}
+		for file in ["a/b/x.rs", "a/y.rs", "a-b/z.rs", "c/d/e/w.rs", "c.rs"] {
+			std::fs::write(dir.join(file), "").unwrap();
+		}
+
+		let ct = task::CancelToken::default();
+		let sequential = ScanOptions::new(false, false);
+		let parallel = ScanOptions { threads: Some(4), ..sequential };
+		let paths = |options| {
+			collect_entries(&dir, options, None, &ct)
+				.unwrap()
+				.entries
+				.into_iter()
+				.map(|entry| entry.path)
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(paths(parallel), paths(sequential));
+	}
+
+	#[test]
+	fn test_piignore_excludes_outside_git() {
+		let dir = TempDir::new("piignore");
+		std::fs::create_dir_all(dir.join("gen")).unwrap();
+		std::fs::write(dir.join(PIIGNORE_FILENAME), "gen/\n").unwrap();
+		std::fs::write(dir.join("gen/out.rs"), "").unwrap();
+		std::fs::write(dir.join("main.rs"), "").unwrap();
+
+		let ct = task::CancelToken::default();
+		let paths = |use_piignore| {
+			let options = ScanOptions { use_piignore, ..ScanOptions::new(false, false) };
+			collect_entries(&dir, options, None, &ct)
+				.unwrap()
+				.entries
+				.into_iter()
+				.map(|entry| entry.path)
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(paths(true), ["main.rs"]);
+		assert_eq!(paths(false), ["gen", "gen/out.rs", "main.rs"]);
+	}
+
+	#[test]
+	fn test_reinclude_adds_back_ignored_entries() {
+		let dir = TempDir::new("reinclude");
+		for sub in [".git", "dist", "sub/dist", "build/dist"] {
+			std::fs::create_dir_all(dir.join(sub)).unwrap();
+		}
+		std::fs::write(dir.join(".gitignore"), "dist/\nbuild/\n*.log\n").unwrap();
+		for file in ["dist/a.js", "sub/dist/b.js", "build/dist/c.js", "a.log", "main.rs"] {
+			std::fs::write(dir.join(file), "").unwrap();
+		}
+		let root = std::fs::canonicalize(&dir).unwrap();
+
+		let ct = task::CancelToken::default();
+		let paths = |reinclude: &[&str]| {
+			let reinclude: Vec<String> = reinclude.iter().map(|line| line.to_string()).collect();
+			get_or_scan(&root, ScanOptions::new(false, true), &reinclude, "test-reinclude", None, &ct)
+				.unwrap()
+				.entries
+				.into_iter()
+				.map(|entry| entry.path)
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(paths(&[]), ["main.rs", "sub"]);
+		// Ignored `build/` hides its `dist` as it would in git.
+		assert_eq!(paths(&["!dist/"]), [
+			"dist",
+			"dist/a.js",
+			"main.rs",
+			"sub",
+			"sub/dist",
+			"sub/dist/b.js"
+		]);
+		assert_eq!(paths(&["/dist", "*.log"]), ["a.log", "dist", "dist/a.js", "main.rs", "sub"]);
+		assert_eq!(paths(&[]), ["main.rs", "sub"]);
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn test_reinclude_keeps_scan_limits() {
+		let dir = TempDir::new("reinclude-limits");
+		for sub in ["root/.git", "root/dist", "out/dist"] {
+			std::fs::create_dir_all(dir.join(sub)).unwrap();
+		}
+		std::fs::write(dir.join("root/.gitignore"), "dist/\n").unwrap();
+		for file in ["root/main.rs", "root/dist/a.js", "out/dist/b.js"] {
+			std::fs::write(dir.join(file), "").unwrap();
+		}
+		std::fs::hard_link(dir.join("root/main.rs"), dir.join("root/dist/main.rs")).unwrap();
+		std::os::unix::fs::symlink(dir.join("out"), dir.join("root/link")).unwrap();
+		let root = std::fs::canonicalize(dir.join("root")).unwrap();
+
+		let ct = task::CancelToken::default();
+		let paths = |options| {
+			scan_root(&root, options, &["!dist/".to_string()], None, &ct)
+				.unwrap()
+				.entries
+				.into_iter()
+				.map(|entry| entry.path)
+				.collect::<Vec<_>>()
+		};
+		let options = ScanOptions::new(false, true);
+		assert_eq!(paths(options), ["dist", "dist/a.js", "dist/main.rs", "link", "main.rs"]);
+		// The hard link is the physical file the main scan already recorded.
+		let deduped = ScanOptions { dedup_by_inode: true, ..options };
+		assert_eq!(paths(deduped), ["dist", "dist/a.js", "link", "main.rs"]);
+
+		let followed = ScanOptions { follow_links: true, ..options };
+		assert_eq!(paths(followed), [
+			"dist",
+			"dist/a.js",
+			"dist/main.rs",
+			"link",
+			"link/dist",
+			"link/dist/b.js",
+			"main.rs"
+		]);
+		// `link/dist` sits one symlink down and its contents are out of reach.
+		let shallow = ScanOptions { max_symlink_depth: Some(0), ..followed };
+		assert_eq!(paths(shallow), ["dist", "dist/a.js", "dist/main.rs", "link", "main.rs"]);
+	}
+
+	#[test]
+	fn test_scan_progress_reports_every_interval() {
+		let dir = TempDir::new("progress");
+		for i in 0..2500 {
+			std::fs::write(dir.join(format!("f{i}.rs")), "").unwrap();
+		}
+
+		let ct = task::CancelToken::default();
+		let sequential = ScanOptions::new(false, false);
+		let parallel = ScanOptions { threads: Some(4), ..sequential };
+		for options in [sequential, parallel] {
+			let reported = Mutex::new(Vec::new());
+			let progress = |discovered| reported.lock().push(discovered);
+			collect_entries(&dir, options, Some(&progress), &ct).unwrap();
+			let mut reported = reported.into_inner();
+			reported.sort_unstable();
+			assert_eq!(reported, [1000, 2000]);
+		}
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn test_max_symlink_depth() {
+		let dir = TempDir::new("symlink-depth");
+		for sub in ["root", "out1", "out2"] {
+			std::fs::create_dir_all(dir.join(sub)).unwrap();
+		}
+		std::fs::write(dir.join("out2/f.rs"), "").unwrap();
+		std::os::unix::fs::symlink(dir.join("out1"), dir.join("root/l1")).unwrap();
+		std::os::unix::fs::symlink(dir.join("out2"), dir.join("out1/l2")).unwrap();
+
+		let ct = task::CancelToken::default();
+		let paths = |max_symlink_depth| {
+			let options = ScanOptions {
+				follow_links: true,
+				max_symlink_depth: Some(max_symlink_depth),
+				..ScanOptions::new(false, false)
+			};
+			collect_entries(&dir.join("root"), options, None, &ct)
+				.unwrap()
+				.entries
+				.into_iter()
+				.map(|entry| entry.path)
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(paths(0), ["l1"]);
+		assert_eq!(paths(1), ["l1", "l1/l2"]);
+		assert_eq!(paths(2), ["l1", "l1/l2", "l1/l2/f.rs"]);
+	}
+
+	#[test]
+	fn test_discovery_stamps_only_fresh_scans() {
+		let dir = TempDir::new("discovery");
+		std::fs::write(dir.join("a.rs"), "").unwrap();
+		let root = resolve_search_path(dir.to_str().unwrap()).unwrap();
+		let ct = task::CancelToken::default();
+		let options = ScanOptions { record_discovery: true, ..ScanOptions::new(false, false) };
+
+		let fresh = get_or_scan(&root, options, &[], "test-discovery", None, &ct).unwrap();
+		assert!(!fresh.from_cache);
+		assert!(fresh.entries[0].discovered_at_ms.is_some());
+		let cached = get_or_scan(&root, options, &[], "test-discovery", None, &ct).unwrap();
+		assert!(cached.from_cache);
+		assert_eq!(cached.entries[0].discovered_at_ms, None);
+		invalidate_path(&root);
+	}
+
+	#[test]
+	fn test_fs_scan_cache_entries_for_root() {
+		let dir = TempDir::new("cache-dump");
+		let root = resolve_search_path(dir.to_str().unwrap()).unwrap();
+		let path = root.to_string_lossy().into_owned();
+		assert!(fs_scan_cache_entries_for_root(path.clone(), None, None, None).is_none());
+
+		let scan = ScanResult {
+			entries: vec![GlobMatch::new("a.rs".to_string(), FileType::File, None)],
+			..ScanResult::default()
+		};
+		let key = CacheKey::new(&root, ScanOptions::new(false, true), SHARED_NAMESPACE);
+		store_scan(key, root_epoch(&root), Instant::now(), &scan);
+		let entries = fs_scan_cache_entries_for_root(path, None, None, None).unwrap();
+		assert_eq!(
+			entries
+				.iter()
+				.map(|entry| entry.path.as_str())
+				.collect::<Vec<_>>(),
+			["a.rs"]
+		);
+		invalidate_path(&root);
+	}
+
+	#[test]
+	fn test_eviction_is_per_namespace() {
+		let cache = DashMap::new();
+		let options = ScanOptions::new(false, true);
+		let start = Instant::now();
+		let store = |root: &Path, namespace: &str, created_ms: u64| {
+			let key = CacheKey::new(root, options, namespace);
+			cache.insert(key.clone(), cache_entry(start + Duration::from_millis(created_ms)));
+			evict_oldest(&cache, &DashSet::new(), namespace, 2);
+			key
+		};
+		let picker = store(Path::new("/picker"), "picker", 0);
+		let indexer: Vec<_> = (1..=3)
+			.map(|index| store(Path::new(&format!("/{index}")), "indexer", index))
+			.collect();
+		assert!(cache.contains_key(&picker));
+		assert!(!cache.contains_key(&indexer[0]));
+		assert!(cache.contains_key(&indexer[1]) && cache.contains_key(&indexer[2]));
+	}
+
+	#[test]
+	fn test_evict_oldest_skips_pinned_entries() {
+		let cache = DashMap::new();
+		let pinned = DashSet::new();
+		let options = ScanOptions::new(false, true);
+		let start = Instant::now();
+		let keys: Vec<_> = (0..4)
+			.map(|index| {
+				let key = CacheKey::new(Path::new(&format!("/{index}")), options, "test");
+				cache.insert(key.clone(), cache_entry(start + Duration::from_millis(index)));
+				key
+			})
+			.collect();
+		pinned.insert(keys[0].clone());
+		let evicted = evict_oldest(&cache, &pinned, "test", 3).map(|(key, _)| key);
+		assert_eq!(evicted.as_ref(), Some(&keys[1]));
+		assert!(evict_oldest(&cache, &pinned, "test", 3).is_none());
+		// Once everything is pinned, the oldest pinned entry goes.
+		for key in &keys {
+			pinned.insert(key.clone());
+		}
+		let evicted = evict_oldest(&cache, &pinned, "test", 2).map(|(key, _)| key);
+		assert_eq!(evicted.as_ref(), Some(&keys[0]));
+	}
+
+	#[test]
+	fn test_rename_path_moves_pins() {
+		let options = ScanOptions::new(false, true);
+		let from = Path::new("/pi-natives-test/rename-pin/old");
+		let to = Path::new("/pi-natives-test/rename-pin/new");
+		let pinned = CacheKey::new(&from.join("sub"), options, "test-rename-pin");
+		PINNED_KEYS.insert(pinned.clone());
+		rename_path(from, to);
+		let moved = CacheKey::new(&to.join("sub"), options, "test-rename-pin");
+		assert!(!PINNED_KEYS.contains(&pinned));
+		assert!(PINNED_KEYS.remove(&moved).is_some());
+	}
+
+	#[test]
+	fn test_prune_expired_keeps_fresh_entries() {
+		let options = ScanOptions::new(false, true);
+		let now = Instant::now();
+		let store = |name: &str, age: Duration| {
+			let root = Path::new("/pi-natives-test/prune").join(name);
+			let key = CacheKey::new(&root, options, "test-prune");
+			let created_at = now.checked_sub(age).unwrap_or(now);
+			store_scan(key.clone(), root_epoch(&root), created_at, &ScanResult::default());
+			key
+		};
+		// Other tests' entries are fresh, so a minute-long TTL leaves them alone.
+		let stale = store("stale", Duration::from_mins(2));
+		let fresh = store("fresh", Duration::ZERO);
+		assert!(prune_expired(Duration::from_mins(1)) >= 1);
+		assert!(!FS_CACHE.contains_key(&stale));
+		assert!(FS_CACHE.contains_key(&fresh));
+	}
+
+	#[test]
+	fn test_keys_over_budget_evicts_oldest_across_namespaces() {
+		let options = ScanOptions::new(false, true);
+		let start = Instant::now();
+		let entry = |name: &str, namespace: &str, created_ms: u64, bytes: usize| {
+			let key = CacheKey::new(Path::new(name), options, namespace);
+			(key, start + Duration::from_millis(created_ms), bytes)
+		};
+		let entries = vec![
+			entry("/b", "picker", 2, 40),
+			entry("/a", "indexer", 1, 30),
+			entry("/c", "indexer", 3, 50),
+		];
+		let evicted = |max_bytes| {
+			keys_over_budget(entries.clone(), max_bytes)
+				.into_iter()
+				.map(|key| key.root)
+				.collect::<Vec<_>>()
+		};
+		assert!(evicted(120).is_empty());
+		assert_eq!(evicted(100), [Path::new("/a")]);
+		assert_eq!(evicted(50), [Path::new("/a"), Path::new("/b")]);
+	}
+}
@@ -14,9 +14,18 @@
 //! // JS: await native.glob({ pattern: "*.rs", path: "." })
 //! ```
 
-use std::path::Path;
+use std::{
+	borrow::Cow,
+	collections::{BinaryHeap, HashMap, HashSet},
+	io::Read,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::{Duration, Instant},
+};
 
-use globset::{Glob, GlobSet, GlobSetBuilder};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use grep_matcher::Matcher;
+use grep_regex::RegexMatcher;
 use napi::{
 	bindgen_prelude::*,
 	threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode},
@@ -25,238 +34,3428 @@ use napi_derive::napi;
 
 // Re-export entry types so existing `glob::FileType` / `glob::GlobMatch` paths still work.
 pub use crate::fs_cache::{FileType, GlobMatch};
-use crate::{fs_cache, task};
+use crate::{
+	error::{self, ErrorCode},
+	fd, fs_cache, grep, task,
+};
 
 /// Input options for `glob`, including traversal, filtering, and cancellation.
+#[derive(Default)]
 #[napi(object)]
 pub struct GlobOptions<'env> {
-	/// Glob pattern to match (e.g., "*.ts").
-	pub pattern:              String,
+	/// Glob pattern to match (e.g., "*.ts"). A trailing slash, as in
+	/// `build/`, matches only directories.
+	pub pattern:                  String,
 	/// Directory to search.
-	pub path:                 String,
+	pub path:                     String,
 	/// Filter by file type: "file", "dir", or "symlink".
 	#[napi(js_name = "fileType")]
-	pub file_type:            Option<FileType>,
+	pub file_type:                Option<FileType>,
+	/// Keep entries of any of these types, e.g. files and symlinks but not
+	/// directories. Merged with `fileType` when both are set; an empty list
+	/// applies no filter.
+	#[napi(js_name = "fileTypes")]
+	pub file_types:               Option<Vec<FileType>>,
 	/// Include hidden files (default: false).
-	pub hidden:               Option<bool>,
+	pub hidden:                   Option<bool>,
 	/// Maximum number of results to return.
 	#[napi(js_name = "maxResults")]
-	pub max_results:          Option<u32>,
+	pub max_results:              Option<u32>,
 	/// Respect .gitignore files (default: true).
-	pub gitignore:            Option<bool>,
+	pub gitignore:                Option<bool>,
 	/// Enable shared filesystem scan cache (default: false).
-	pub cache:                Option<bool>,
+	pub cache:                    Option<bool>,
 	/// Sort results by mtime (most recent first) before applying limit.
 	#[napi(js_name = "sortByMtime")]
-	pub sort_by_mtime:        Option<bool>,
+	pub sort_by_mtime:            Option<bool>,
+	/// Direction of the active sort: `"desc"` (default) or `"asc"`; other
+	/// values fail with `INVALID_PATTERN`. Ties are broken by path in the same
+	/// direction.
+	#[napi(js_name = "sortDirection")]
+	pub sort_direction:           Option<String>,
 	/// Include `node_modules` entries when the pattern does not explicitly
 	/// mention them.
 	#[napi(js_name = "includeNodeModules")]
-	pub include_node_modules: Option<bool>,
+	pub include_node_modules:     Option<bool>,
 	/// Abort signal for cancelling the operation.
-	pub signal:               Option<Unknown<'env>>,
+	pub signal:                   Option<Unknown<'env>>,
 	/// Timeout in milliseconds for the operation.
 	#[napi(js_name = "timeoutMs")]
-	pub timeout_ms:           Option<u32>,
+	pub timeout_ms:               Option<u32>,
+	/// Report each physical file once, skipping paths (e.g. symlinks or hard
+	/// links) that resolve to an already-seen inode (default: false).
+	#[napi(js_name = "dedupByInode")]
+	pub dedup_by_inode:           Option<bool>,
+	/// Only stream matches through the callback; the returned `matches` is
+	/// left empty and only `totalMatches` is populated (default: false).
+	#[napi(js_name = "streamOnly")]
+	pub stream_only:              Option<bool>,
+	/// Treat backslashes in the pattern as path separators on every platform
+	/// (default: false; always on for Windows). Disables backslash escapes.
+	#[napi(js_name = "normalizeSeparators")]
+	pub normalize_separators:     Option<bool>,
+	/// Wildcard semantics: `"default"` and `"literal-star-crosses-dirs"` let
+	/// `*` match across `/`; `"bash"` confines `*` and `?` to one path
+	/// component, leaving `**` to cross directories. Other values fail with
+	/// `INVALID_PATTERN`.
+	#[napi(js_name = "globSyntax")]
+	pub glob_syntax:              Option<String>,
+	/// Only match directories with no descendants in the scan (default: false).
+	#[napi(js_name = "emptyDirsOnly")]
+	pub empty_dirs_only:          Option<bool>,
+	/// Relative match paths to rank first, in list order, ahead of the normal
+	/// ordering (applied after `sortByMtime`, before `maxResults`).
+	#[napi(js_name = "priorityPaths")]
+	pub priority_paths:           Option<Vec<String>>,
+	/// Store the fresh scan of an uncached (`cache: false`) query so later
+	/// cached queries can reuse it (default: false).
+	#[napi(js_name = "populateCache")]
+	pub populate_cache:           Option<bool>,
+	/// Relative paths or globs to add back even when ignore rules exclude them
+	/// (e.g. a committed `dist/manifest.json`). Forced entries must still match
+	/// `pattern` and the other filters. A glob must start with a literal
+	/// directory (`dist/**/*.js`, not `**/dist/*.js`), which bounds the walk
+	/// that finds its matches; use `reinclude` for patterns at any depth.
+	#[napi(js_name = "forceInclude")]
+	pub force_include:            Option<Vec<String>>,
+	/// Stop scanning after this many entries, regardless of time; see
+	/// `scanCapped` on the result.
+	#[napi(js_name = "maxScanEntries")]
+	pub max_scan_entries:         Option<u32>,
+	/// Return the ancestor directories (up to the root) of files matching
+	/// `pattern` instead of the files themselves (default: false).
+	#[napi(js_name = "dirsWithMatches")]
+	pub dirs_with_matches:        Option<bool>,
+	/// Skip matches whose relative path is longer than this many characters
+	/// (Unicode scalar values); see `skippedLongPaths` on the result.
+	#[napi(js_name = "maxPathLength")]
+	pub max_path_length:          Option<u32>,
+	/// Only accept entries exactly this many directories below the root
+	/// (0 = immediate children).
+	#[napi(js_name = "exactDepth")]
+	pub exact_depth:              Option<u32>,
+	/// Accept a file as `path`, returning it (by file name) if it passes the
+	/// filters instead of failing with `NOT_A_DIRECTORY` (default: false).
+	#[napi(js_name = "allowFileRoot")]
+	pub allow_file_root:          Option<bool>,
+	/// Only match entries whose mtime is at or after this time (milliseconds
+	/// since epoch), e.g. a previous result's `snapshotMs`.
+	#[napi(js_name = "newerThanSnapshotMs")]
+	pub newer_than_snapshot_ms:   Option<f64>,
+	/// Set `snapshotMs` on the result (default: false).
+	#[napi(js_name = "returnSnapshot")]
+	pub return_snapshot:          Option<bool>,
+	/// Return matches bucketed by their first path component in `groups`
+	/// instead of `matches` (default: false). Files directly under the root
+	/// are grouped under `""`.
+	#[napi(js_name = "groupByTopDir")]
+	pub group_by_top_dir:         Option<bool>,
+	/// With `groupByTopDir`: keep at most this many matches per group,
+	/// applied after `maxResults`.
+	#[napi(js_name = "maxResultsPerGroup")]
+	pub max_results_per_group:    Option<u32>,
+	/// Accept at most this many matches per parent directory, in walk order,
+	/// for samples spread across the tree (default: unlimited).
+	#[napi(js_name = "maxResultsPerDir")]
+	pub max_results_per_dir:      Option<u32>,
+	/// Keep only the first match for each file name, in walk order (or in
+	/// the active sort order), dropping same-named matches in other
+	/// directories (default: false).
+	#[napi(js_name = "uniqueByBasename")]
+	pub unique_by_basename:       Option<bool>,
+	/// Replace the matches under a directory with a single directory entry
+	/// (see `collapsedCount`) when more than this many would be listed there.
+	#[napi(js_name = "collapseThreshold")]
+	pub collapse_threshold:       Option<u32>,
+	/// Stop collecting matches once their paths would total more than this
+	/// many bytes; see `truncated` on the result.
+	#[napi(js_name = "maxResultBytes")]
+	pub max_result_bytes:         Option<u32>,
+	/// Only match files of this language (e.g. `"rust"`, `"typescript"`),
+	/// using the same table as grep's `type` filter.
+	pub language:                 Option<String>,
+	/// Extensions (without the dot) to use for `language` instead of the
+	/// built-in table; also usable on its own.
+	#[napi(js_name = "languageExtensions")]
+	pub language_extensions:      Option<Vec<String>>,
+	/// Fuzzy query matched against each entry's name and path instead of
+	/// `pattern`; results are ranked by score, best first.
+	pub fuzzy:                    Option<String>,
+	/// Return matches as a nested tree under `tree` instead of a flat list
+	/// (default: false).
+	#[napi(js_name = "asTree")]
+	pub as_tree:                  Option<bool>,
+	/// What a `timeoutMs` expiry does: `"error"` rejects (default), `"partial"`
+	/// resolves with the matches found so far and `timedOut` set. Other values
+	/// fail with `INVALID_PATTERN`.
+	#[napi(js_name = "timeoutBehavior")]
+	pub timeout_behavior:         Option<String>,
+	/// Regex the final path component must also match (e.g. `^test_.*\.rs$`),
+	/// applied after `pattern`.
+	#[napi(js_name = "nameRegex")]
+	pub name_regex:               Option<String>,
+	/// Cache namespace with its own entry cap, so unrelated callers don't
+	/// evict each other's scans (default: the shared namespace).
+	#[napi(js_name = "cacheNamespace")]
+	pub cache_namespace:          Option<String>,
+	/// Descend into symlinked directories (default: false); loops are skipped
+	/// and counted in `symlinkLoopsDetected`.
+	#[napi(js_name = "followSymlinks")]
+	pub follow_symlinks:          Option<bool>,
+	/// With `followSymlinks`, stop descending once a path has crossed this
+	/// many symlinks (default: unlimited).
+	#[napi(js_name = "maxSymlinkDepth")]
+	pub max_symlink_depth:        Option<u32>,
+	/// Walk the tree on multiple threads (default: false). Results keep the
+	/// sequential order.
+	pub parallel:                 Option<bool>,
+	/// Worker threads for `parallel` walks (default: 0, chosen
+	/// automatically).
+	pub threads:                  Option<u32>,
+	/// Only match files `git status` reports as modified, staged, or
+	/// untracked (default: false). Fails with `NOT_A_GIT_REPO` outside a
+	/// repository.
+	#[napi(js_name = "onlyModifiedInGit")]
+	pub only_modified_in_git:     Option<bool>,
+	/// Fingerprint the returned matches in `resultHash` (default: false).
+	#[napi(js_name = "resultHash")]
+	pub result_hash:              Option<bool>,
+	/// Set `childCount` on directory matches to the number of scanned
+	/// immediate children (default: false).
+	#[napi(js_name = "includeChildCount")]
+	pub include_child_count:      Option<bool>,
+	/// Call the match callback at most once per this many milliseconds,
+	/// skipping callbacks for matches in between; `matches` still lists every
+	/// match (default: no throttling).
+	#[napi(js_name = "callbackMinIntervalMs")]
+	pub callback_min_interval_ms: Option<u32>,
+	/// Set `orderIndex` on each match to its position in the scan's walk
+	/// order, to see which entries `maxResults` kept (default: false).
+	#[napi(js_name = "includeOrderIndex")]
+	pub include_order_index:      Option<bool>,
+	/// Only match files git tracks, excluding untracked and ignored files
+	/// (default: false). Combines with `onlyModifiedInGit`; fails with
+	/// `NOT_A_GIT_REPO` outside a repository.
+	#[napi(js_name = "onlyGitTracked")]
+	pub only_git_tracked:         Option<bool>,
+	/// Prefix patterns without a `/` with `**/` so they match at any depth
+	/// (default: true). When false, `*.rs` matches only direct children of
+	/// the root, with its wildcards kept within one component; `exactDepth`
+	/// still applies on top, so any depth above 0 then matches nothing.
+	#[napi(js_name = "autoRecursive")]
+	pub auto_recursive:           Option<bool>,
+	/// Result shape: `"objects"` (default) fills `matches`; `"paths"` returns
+	/// the relative paths joined by newlines in `paths`, leaving `matches`
+	/// empty, to skip per-match object marshaling; `"buffer"` packs paths,
+	/// types, and mtimes into `buffer` (see [`GlobResult::buffer`]). Other
+	/// values fail with `INVALID_PATTERN`.
+	pub format:                   Option<String>,
+	/// Match patterns against each entry's absolute, symlink-resolved path
+	/// instead of its path relative to the root (default: false), e.g.
+	/// `**/vendor/**` to catch entries whose links point into a vendor tree.
+	/// Patterns should be absolute or start with `**`. Costs a `realpath`
+	/// per entry; ignored by `globMatchEntries`, which has no root.
+	#[napi(js_name = "matchResolvedPath")]
+	pub match_resolved_path:      Option<bool>,
+	/// Only keep symlinks whose target is missing, to find dangling links
+	/// (default: false). Ignored by `globMatchEntries`, which has no root.
+	#[napi(js_name = "brokenSymlinksOnly")]
+	pub broken_symlinks_only:     Option<bool>,
+	/// Move directories ahead of files and symlinks (`false`: behind them),
+	/// keeping the existing order within each group. Unset leaves the order
+	/// alone.
+	#[napi(js_name = "dirsFirst")]
+	pub dirs_first:               Option<bool>,
+	/// Count scanned and matched non-directory entries per extension into
+	/// `extensionBreakdown` (default: false). Filtering then visits every
+	/// entry instead of stopping at `maxResults`.
+	#[napi(js_name = "extensionBreakdown")]
+	pub extension_breakdown:      Option<bool>,
+	/// Trust an absolute `path` as an existing, canonical directory and skip
+	/// resolving it (default: false), saving a `stat` and `realpath` per call.
+	/// A path that doesn't exist then yields an empty result instead of
+	/// `PATH_NOT_FOUND`, and `allowFileRoot` no longer applies.
+	#[napi(js_name = "rootAlreadyResolved")]
+	pub root_already_resolved:    Option<bool>,
+	/// Set `discoveredAtMs` on each match to when the walk found it, relative
+	/// to the walk's start (default: false). Only fresh scans carry stamps;
+	/// matches served from the cache have none.
+	#[napi(js_name = "includeDiscoveredAtMs")]
+	pub include_discovered_at_ms: Option<bool>,
+	/// Called with the number of entries discovered so far, every 1000 entries,
+	/// while a cache miss walks the tree. Stops once the walk is cancelled.
+	#[napi(js_name = "onScanProgress", ts_type = "((entries: number) => void) | undefined | null")]
+	pub on_scan_progress:         Option<ThreadsafeFunction<u32>>,
+	/// Respect `.piignore` files (default: true), on top of `gitignore`. They
+	/// use `.gitignore` syntax and apply outside git repositories too.
+	#[napi(js_name = "usePiignore")]
+	pub use_piignore:             Option<bool>,
+	/// Return paths with `\` separators on Windows (default: false). Matching
+	/// still sees `/`-separated paths; no effect on other platforms.
+	#[napi(js_name = "nativeSeparators")]
+	pub native_separators:        Option<bool>,
+	/// Bucket matches by mtime into `timeBuckets` (`matches` is then empty),
+	/// newest first within each bucket; implies `sortByMtime` descending.
+	#[napi(js_name = "timeBuckets")]
+	pub time_buckets:             Option<bool>,
+	/// Set `realPath` on each match to its absolute, symlink-resolved path
+	/// (default: false). Costs a `realpath` per match; no effect in
+	/// `globMatchEntries`.
+	pub canonicalize:             Option<bool>,
+	/// Fail with `NO_MATCH` instead of returning an empty result, like
+	/// `shopt -s failglob` (default: false). Checked after the empty-result
+	/// recheck; a partial result cut short by a timeout is returned as-is.
+	#[napi(js_name = "requireMatch")]
+	pub require_match:            Option<bool>,
+	/// Keep only regular files whose first bytes sniff as this type:
+	/// `"image"`, `"archive"`, `"pdf"`, `"text"`, or `"binary"` (anything but
+	/// text). Reads a 512-byte header per candidate, so combine with cheaper
+	/// filters where possible. No effect in `globMatchEntries`. Other values
+	/// fail with `INVALID_PATTERN`.
+	#[napi(js_name = "contentType")]
+	pub content_type:             Option<String>,
+	/// Drop matches below another matched directory (default: false),
+	/// leaving a minimal covering set for operations that recurse on their
+	/// own. Filtering then visits every entry instead of stopping at
+	/// `maxResults`.
+	#[napi(js_name = "topmostOnly")]
+	pub topmost_only:             Option<bool>,
+	/// Keep only candidates this predicate returns `true` for. Crossing into
+	/// JS per entry is expensive, so it only runs on entries that passed every
+	/// native filter; no effect in `globMatchEntries`.
+	#[napi(ts_type = "((match: GlobMatch) => boolean) | undefined | null")]
+	pub filter:                   Option<GlobFilter>,
+	/// Stay on the root's filesystem (default: false): directories on other
+	/// mounts, such as NFS/SMB shares under the project, are listed but not
+	/// entered.
+	#[napi(js_name = "sameFilesystem")]
+	pub same_filesystem:          Option<bool>,
+	/// Gitignore-style patterns whose ignored matches are added back, as if
+	/// appended to `.gitignore` as negations (`"dist/"` and `"!dist/"` both
+	/// re-include `dist` and everything below it). As in git, nothing comes
+	/// back from below an ignored directory that isn't itself re-included.
+	/// The patterns are part of the scan's cache key.
+	pub reinclude:                Option<Vec<String>>,
+	/// Set `depth` on each match to the number of directories between it and
+	/// the root, as `exactDepth` counts them (default: false).
+	#[napi(js_name = "includeDepth")]
+	pub include_depth:            Option<bool>,
+	/// Prefix every output path with this name and a separator, e.g.
+	/// `"@workspace"` for `"@workspace/src/a.rs"`, in place of the bare
+	/// relative path. Only the output changes; matching and the cache still
+	/// use root-relative paths.
+	#[napi(js_name = "rootAlias")]
+	pub root_alias:               Option<String>,
+	/// Set `patternIndex` on each match to the index of the pattern that
+	/// matched it (default: false). `pattern` is a single glob, so this is
+	/// always 0; it lets callers tag results the same way once a query takes
+	/// several patterns.
+	#[napi(js_name = "includeMatchedPatternIndex")]
+	pub include_pattern_index:    Option<bool>,
+}
+
+/// `filter` predicate, called with just the candidate match.
+pub type GlobFilter = ThreadsafeFunction<GlobMatch, bool, GlobMatch, Status, false>;
+
+/// Per-extension entry counts, as reported by `extensionBreakdown`.
+#[derive(Clone, Copy, Default)]
+#[napi(object)]
+pub struct ExtensionCounts {
+	/// Entries with this extension that reached the filters.
+	pub scanned: u32,
+	/// Entries with this extension that passed every filter, before
+	/// `maxResults` truncation.
+	pub matched: u32,
+}
+
+/// Matches sharing a first path component.
+#[napi(object)]
+pub struct GlobGroup {
+	/// First path component of every match in the group (`""` for files
+	/// directly under the root).
+	pub dir:     String,
+	/// Matches in result order.
+	pub matches: Vec<GlobMatch>,
+}
+
+/// Matches whose mtime falls in the same `timeBuckets` window.
+#[napi(object)]
+pub struct GlobTimeBucket {
+	/// `"today"` (last 24 hours), `"yesterday"` (24 to 48 hours ago),
+	/// `"thisWeek"` (last 7 days), or `"older"` (including no mtime).
+	pub bucket:  String,
+	/// Matches in result order, newest first.
+	pub matches: Vec<GlobMatch>,
+}
+
+/// A node in the `asTree` result, rooted at the search root.
+#[napi(object)]
+pub struct GlobTreeNode {
+	/// Final path component (`""` for the root).
+	pub name:      String,
+	/// Relative path from the search root (`""` for the root).
+	pub path:      String,
+	/// Filesystem type; intermediate directories are `Dir`.
+	#[napi(js_name = "fileType")]
+	pub file_type: FileType,
+	/// Whether this node is itself a match, as opposed to an ancestor of one.
+	pub matched:   bool,
+	/// Child nodes in result order.
+	pub children:  Vec<Self>,
 }
 
 /// Result payload returned by a glob operation.
 #[napi(object)]
 pub struct GlobResult {
 	/// Matched filesystem entries.
-	pub matches:       Vec<GlobMatch>,
-	/// Number of returned matches (`matches.len()`), clamped to `u32::MAX`.
+	pub matches: Vec<GlobMatch>,
+	/// Number of accepted matches after limits are applied, clamped to
+	/// `u32::MAX`. Equals `matches.len()` unless `streamOnly` is set.
 	pub total_matches: u32,
+	/// Whether the empty-result recheck of a stale cached scan was skipped
+	/// because too little of the timeout budget remained.
+	pub rescan_skipped_due_to_budget: bool,
+	/// Whether the scan stopped at `maxScanEntries`, so matches may be
+	/// incomplete.
+	pub scan_capped: bool,
+	/// Whether `matches` came from a cached scan, as opposed to a fresh walk
+	/// (uncached query or empty-result rescan).
+	pub from_cache: bool,
+	/// Number of otherwise-matching entries skipped by `maxPathLength`.
+	pub skipped_long_paths: u32,
+	/// Whether `matches` stopped early at `maxResultBytes`.
+	pub truncated: bool,
+	/// When `returnSnapshot` is set: time (milliseconds since epoch) the
+	/// scanned entries reflect, to pass as the next `newerThanSnapshotMs`.
+	pub snapshot_ms: Option<f64>,
+	/// Matches bucketed by first path component, when `groupByTopDir` is set
+	/// (`matches` is then empty).
+	pub groups: Option<Vec<GlobGroup>>,
+	/// Matches nested by path component, when `asTree` is set (`matches` is
+	/// then empty).
+	pub tree: Option<GlobTreeNode>,
+	/// Whether `timeoutBehavior: "partial"` cut the query short, so matches
+	/// may be incomplete.
+	pub timed_out: bool,
+	/// Number of symlink loops skipped while walking with `followSymlinks`.
+	pub symlink_loops_detected: u32,
+	/// Whether any `.gitignore` or `.git/info/exclude` rules were found and
+	/// applied; false with `gitignore: false` or outside a git repository.
+	pub gitignore_applied: bool,
+	/// When `resultHash` is set: hex fingerprint of the matched paths, types,
+	/// and mtimes, independent of result order. Equal hashes mean the same
+	/// file set.
+	pub result_hash: Option<String>,
+	/// With `format: "paths"`: the matched relative paths in result order,
+	/// one per line.
+	pub paths: Option<String>,
+	/// With `extensionBreakdown`: counts keyed by extension without the dot
+	/// (`""` for none), covering files and symlinks but not directories.
+	#[napi(js_name = "extensionBreakdown")]
+	pub extension_breakdown: Option<HashMap<String, ExtensionCounts>>,
+	/// Matches bucketed by mtime, when `timeBuckets` is set (`matches` is then
+	/// empty). Empty buckets are omitted.
+	#[napi(js_name = "timeBuckets")]
+	pub time_buckets: Option<Vec<GlobTimeBucket>>,
+	/// With `format: "buffer"`: the matches in result order, each encoded
+	/// little-endian as a `u32` path byte length, the UTF-8 path, a `u8`
+	/// `FileType`, and an `f64` mtime in milliseconds (`NaN` when unknown).
+	pub buffer: Option<Buffer>,
 }
 
-fn build_glob_pattern(glob: &str) -> String {
-	let normalized = if cfg!(windows) && glob.contains('\\') {
-		std::borrow::Cow::Owned(glob.replace('\\', "/"))
+/// Minimum remaining timeout budget required to attempt an empty-result
+/// rescan.
+const MIN_RESCAN_BUDGET_MS: u64 = 100;
+
+/// How far `snapshotMs` trails the scan start. Filesystems stamp mtimes from a
+/// coarse clock that can lag the wall clock by a tick, so a file written just
+/// after the snapshot may otherwise carry an older mtime and be missed.
+const SNAPSHOT_SLACK_MS: f64 = 20.0;
+
+/// Normalizes `glob`, prefixing slash-free patterns with `**/` unless
+/// `auto_recursive` is off.
+///
+/// A trailing slash is dropped, since scanned paths never end in one;
+/// [`is_dir_pattern`] restricts such patterns to directories instead.
+fn build_glob_pattern(glob: &str, normalize_separators: bool, auto_recursive: bool) -> String {
+	let normalized = if (normalize_separators || cfg!(windows)) && glob.contains('\\') {
+		Cow::Owned(glob.replace('\\', "/"))
 	} else {
-		std::borrow::Cow::Borrowed(glob)
+		Cow::Borrowed(glob)
+	};
+	let trimmed = match normalized.trim_end_matches('/') {
+		"" => normalized.as_ref(),
+		trimmed => trimmed,
 	};
-	if normalized.contains('/') || normalized.starts_with("**") {
-		normalized.into_owned()
+	if !auto_recursive || trimmed.contains('/') || trimmed.starts_with("**") {
+		trimmed.to_string()
 	} else {
-		format!("**/{normalized}")
+		format!("**/{trimmed}")
 	}
 }
 
-fn compile_glob(glob: &str) -> Result<GlobSet> {
+/// Whether `glob` ends in a separator, gitignore-style: `build/` matches only
+/// directories named `build`.
+fn is_dir_pattern(glob: &str, normalize_separators: bool) -> bool {
+	let is_separator =
+		|ch: char| ch == '/' || ((normalize_separators || cfg!(windows)) && ch == '\\');
+	glob.ends_with(is_separator) && !glob.trim_end_matches(is_separator).is_empty()
+}
+
+/// Wildcard semantics selected by `globSyntax`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum GlobSyntax {
+	/// `*` and `?` match across `/`.
+	Default,
+	/// `*` and `?` stay within one path component; only `**` crosses `/`.
+	Bash,
+}
+
+/// Whether `globSyntax` keeps `*` and `?` within one path component.
+fn literal_separator(glob_syntax: Option<&str>) -> Result<bool> {
+	let syntax = parse_choice("globSyntax", glob_syntax, GlobSyntax::Default, &[
+		("default", GlobSyntax::Default),
+		("literal-star-crosses-dirs", GlobSyntax::Default),
+		("bash", GlobSyntax::Bash),
+	])?;
+	Ok(syntax == GlobSyntax::Bash)
+}
+
+fn compile_glob(
+	glob: &str,
+	normalize_separators: bool,
+	literal_separator: bool,
+	auto_recursive: bool,
+) -> Result<GlobSet> {
 	let mut builder = GlobSetBuilder::new();
-	let pattern = build_glob_pattern(glob);
-	let glob = Glob::new(&pattern)
-		.map_err(|err| Error::from_reason(format!("Invalid glob pattern: {err}")))?;
-	builder.add(glob);
-	builder
+	let pattern = build_glob_pattern(glob, normalize_separators, auto_recursive);
+	// Without the prefix, a slash-free pattern must not reach below the root.
+	let shallow = !auto_recursive && !pattern.contains('/');
+	let glob = GlobBuilder::new(&pattern)
+		.literal_separator(literal_separator || shallow)
 		.build()
-		.map_err(|err| Error::from_reason(format!("Failed to build glob matcher: {err}")))
+		.map_err(|err| {
+			error::coded(ErrorCode::InvalidPattern, format!("Invalid glob pattern: {err}"))
+		})?;
+	builder.add(glob);
+	builder.build().map_err(|err| {
+		error::coded(ErrorCode::InvalidPattern, format!("Failed to build glob matcher: {err}"))
+	})
+}
+
+/// Result shape selected by `format`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ResultFormat {
+	/// One `GlobMatch` per result in `matches`.
+	Objects,
+	/// Newline-joined relative paths in `paths`.
+	Paths,
+	/// Packed paths, types, and mtimes in `buffer`.
+	Buffer,
 }
 
+const DAY_MS: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+
+/// `timeBuckets` names with the age each bucket ends at, youngest first.
+const TIME_BUCKETS: [(&str, f64); 4] = [
+	("today", DAY_MS),
+	("yesterday", 2.0 * DAY_MS),
+	("thisWeek", 7.0 * DAY_MS),
+	("older", f64::INFINITY),
+];
+
 /// Internal runtime config for a single glob execution.
 struct GlobConfig {
-	root:                  std::path::PathBuf,
+	path:                  String,
 	pattern:               String,
-	include_hidden:        bool,
-	file_type_filter:      Option<FileType>,
+	scan_options:          fs_cache::ScanOptions,
+	/// Accepted entry types; empty accepts all.
+	file_type_filter:      Vec<FileType>,
+	/// The pattern ends in a slash, so only directories match.
+	dirs_only:             bool,
 	max_results:           usize,
-	use_gitignore:         bool,
 	mentions_node_modules: bool,
 	sort_by_mtime:         bool,
+	sort_ascending:        bool,
 	use_cache:             bool,
+	stream_only:           bool,
+	normalize_separators:  bool,
+	literal_separator:     bool,
+	empty_dirs_only:       bool,
+	priority_paths:        Vec<String>,
+	populate_cache:        bool,
+	force_include:         Vec<String>,
+	/// Gitignore-style lines whose ignored matches the scan adds back.
+	reinclude:             Vec<String>,
+	dirs_with_matches:     bool,
+	max_path_length:       Option<usize>,
+	exact_depth:           Option<usize>,
+	allow_file_root:       bool,
+	newer_than_ms:         Option<f64>,
+	return_snapshot:       bool,
+	group_by_top_dir:      bool,
+	max_results_per_group: usize,
+	max_results_per_dir:   Option<usize>,
+	unique_by_basename:    bool,
+	collapse_threshold:    Option<usize>,
+	max_result_bytes:      Option<usize>,
+	language_filter:       Option<grep::TypeFilter>,
+	fuzzy:                 Option<FuzzyQuery>,
+	as_tree:               bool,
+	partial_on_timeout:    bool,
+	name_regex:            Option<String>,
+	cache_namespace:       String,
+	only_modified_in_git:  bool,
+	result_hash:           bool,
+	include_child_count:   bool,
+	callback_interval:     Option<Duration>,
+	include_order_index:   bool,
+	only_git_tracked:      bool,
+	auto_recursive:        bool,
+	format:                ResultFormat,
+	match_resolved_path:   bool,
+	broken_symlinks_only:  bool,
+	dirs_first:            Option<bool>,
+	extension_breakdown:   bool,
+	root_already_resolved: bool,
+	scan_progress:         Option<ThreadsafeFunction<u32>>,
+	/// `nativeSeparators` was set and the OS separator isn't `/`.
+	native_separators:     bool,
+	time_buckets:          bool,
+	canonicalize:          bool,
+	require_match:         bool,
+	content_type:          Option<ContentType>,
+	topmost_only:          bool,
+	filter:                Option<GlobFilter>,
+	include_depth:         bool,
+	root_alias:            Option<String>,
+	include_pattern_index: bool,
+}
+
+/// A `fuzzy` query, pre-normalized for `fd::score_fuzzy_path`.
+struct FuzzyQuery {
+	lower:      String,
+	normalized: String,
+}
+
+impl FuzzyQuery {
+	/// Returns `None` for a blank query, which falls back to `pattern`.
+	fn new(query: &str) -> Option<Self> {
+		let lower = query.trim().to_lowercase();
+		if lower.is_empty() {
+			return None;
+		}
+		let normalized = fd::normalize_fuzzy_text(&lower);
+		Some(Self { lower, normalized })
+	}
+
+	/// Score of `entry`, or 0 when it does not match.
+	fn score(&self, entry: &GlobMatch) -> u32 {
+		fd::score_fuzzy_path(
+			&entry.path,
+			entry.file_type == FileType::Dir,
+			&self.lower,
+			&self.normalized,
+		)
+	}
+}
+
+impl GlobConfig {
+	/// Whether `err` is a timeout that should yield partial results instead.
+	fn is_partial_timeout(&self, err: &Error) -> bool {
+		self.partial_on_timeout && error::code_of(err) == Some(ErrorCode::Timeout)
+	}
+
+	/// Whether filtering must visit every candidate, deferring `max_results`
+	/// truncation to [`order_matches`]: results are reordered or pruned after
+	/// matching, or `extensionBreakdown` counts every entry.
+	const fn defers_truncation(&self) -> bool {
+		self.sort_by_mtime
+			|| !self.priority_paths.is_empty()
+			|| self.fuzzy.is_some()
+			|| self.dirs_first.is_some()
+			|| self.extension_breakdown
+			|| self.topmost_only
+	}
+
+	/// Whether `sortByMtime` can keep only the best `max_results` candidates
+	/// while filtering: nothing else reorders or deduplicates the ranked list.
+	const fn mtime_top_k(&self) -> bool {
+		self.sort_by_mtime
+			&& self.max_results != usize::MAX
+			&& self.fuzzy.is_none()
+			&& self.priority_paths.is_empty()
+			&& self.dirs_first.is_none()
+			&& !self.unique_by_basename
+			&& !self.topmost_only
+			&& !self.stream_only
+	}
+
+	/// FNV-1a fingerprint of `matches` when `resultHash` is set, stable across
+	/// runs and result order.
+	fn result_hash(&self, matches: &[GlobMatch]) -> Option<String> {
+		const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+		const PRIME: u64 = 0x0100_0000_01b3;
+
+		if !self.result_hash {
+			return None;
+		}
+		let mut sorted: Vec<&GlobMatch> = matches.iter().collect();
+		sorted.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+		let mut hash = OFFSET_BASIS;
+		for entry in sorted {
+			let mtime = entry.mtime.map_or(u64::MAX, f64::to_bits);
+			// The NUL separator keeps adjacent paths from running together.
+			let fields: [&[u8]; 3] =
+				[entry.path.as_bytes(), &[0, entry.file_type as u8], &mtime.to_le_bytes()];
+			for byte in fields.into_iter().flatten() {
+				hash = (hash ^ u64::from(*byte)).wrapping_mul(PRIME);
+			}
+		}
+		Some(format!("{hash:016x}"))
+	}
+
+	/// Joins the match paths by newlines for `format: "paths"`; returns the
+	/// remaining list (then empty) and the joined paths for the result.
+	fn format_matches(&self, matches: Vec<GlobMatch>) -> (Vec<GlobMatch>, Option<String>) {
+		if self.format != ResultFormat::Paths {
+			return (matches, None);
+		}
+		let mut paths = String::with_capacity(matches.iter().map(|entry| entry.path.len() + 1).sum());
+		for (index, entry) in matches.iter().enumerate() {
+			if index > 0 {
+				paths.push('\n');
+			}
+			paths.push_str(&entry.path);
+		}
+		(Vec::new(), Some(paths))
+	}
+
+	/// Packs `matches` into the `format: "buffer"` layout, applying
+	/// [`Self::output_path`] first since the result pass can't reach inside it.
+	fn encode_matches(&self, matches: Vec<GlobMatch>) -> (Vec<GlobMatch>, Option<Buffer>) {
+		if self.format != ResultFormat::Buffer {
+			return (matches, None);
+		}
+		let mut buffer = Vec::with_capacity(matches.iter().map(|entry| entry.path.len() + 13).sum());
+		for mut entry in matches {
+			self.output_path(&mut entry.path);
+			let len = u32::try_from(entry.path.len()).unwrap_or(u32::MAX);
+			buffer.extend_from_slice(&len.to_le_bytes());
+			buffer.extend_from_slice(entry.path.as_bytes());
+			buffer.push(entry.file_type as u8);
+			buffer.extend_from_slice(&entry.mtime.unwrap_or(f64::NAN).to_le_bytes());
+		}
+		(Vec::new(), Some(buffer.into()))
+	}
+
+	/// Errors with `NO_MATCH` when `requireMatch` is set and a complete
+	/// query matched nothing.
+	fn require_match(&self, matched: usize, timed_out: bool) -> Result<()> {
+		if self.require_match && matched == 0 && !timed_out {
+			return Err(error::coded(
+				ErrorCode::NoMatch,
+				format!("No matches for pattern: {}", self.pattern),
+			));
+		}
+		Ok(())
+	}
+
+	/// Rewrites a `/`-separated result path for output: the OS separator when
+	/// `nativeSeparators` applies, then the `rootAlias` prefix.
+	fn output_path(&self, path: &mut String) {
+		let separator = if self.native_separators {
+			std::path::MAIN_SEPARATOR_STR
+		} else {
+			"/"
+		};
+		if self.native_separators && path.contains('/') {
+			*path = path.replace('/', separator);
+		}
+		if let Some(alias) = &self.root_alias {
+			*path = if path.is_empty() {
+				alias.clone()
+			} else {
+				format!("{alias}{separator}{path}")
+			};
+		}
+	}
+
+	/// Applies [`Self::output_path`] to every path in a finished `result`.
+	/// Runs last, since grouping and trees split paths on `/`.
+	fn output_result(&self, mut result: GlobResult) -> GlobResult {
+		if !self.native_separators && self.root_alias.is_none() {
+			return result;
+		}
+		let groups = result.groups.iter_mut().flatten();
+		let buckets = result.time_buckets.iter_mut().flatten();
+		for entry in result
+			.matches
+			.iter_mut()
+			.chain(groups.flat_map(|group| &mut group.matches))
+			.chain(buckets.flat_map(|bucket| &mut bucket.matches))
+		{
+			self.output_path(&mut entry.path);
+		}
+		if let Some(tree) = &mut result.tree {
+			tree.visit_mut(&mut |node| self.output_path(&mut node.path));
+		}
+		if let Some(paths) = result.paths.as_mut().filter(|paths| !paths.is_empty()) {
+			*paths = paths
+				.split('\n')
+				.map(|line| {
+					let mut line = line.to_string();
+					self.output_path(&mut line);
+					line
+				})
+				.collect::<Vec<_>>()
+				.join("\n");
+		}
+		result
+	}
+
+	/// Moves `matches` into top-level directory groups when grouping is
+	/// enabled; returns the flat list and groups for the result.
+	fn group_matches(&self, matches: Vec<GlobMatch>) -> (Vec<GlobMatch>, Option<Vec<GlobGroup>>) {
+		if !self.group_by_top_dir {
+			return (matches, None);
+		}
+		let mut groups: Vec<GlobGroup> = Vec::new();
+		let mut index_by_dir: HashMap<String, usize> = HashMap::new();
+		for entry in matches {
+			let dir = match entry.path.split_once('/') {
+				Some((first, _)) => first,
+				// A top-level directory heads its own group.
+				None if entry.file_type == FileType::Dir => entry.path.as_str(),
+				None => "",
+			};
+			let index = *index_by_dir.entry(dir.to_string()).or_insert_with(|| {
+				groups.push(GlobGroup { dir: dir.to_string(), matches: Vec::new() });
+				groups.len() - 1
+			});
+			let group = &mut groups[index];
+			if group.matches.len() < self.max_results_per_group {
+				group.matches.push(entry);
+			}
+		}
+		(Vec::new(), Some(groups))
+	}
+
+	/// Moves `matches` into mtime buckets relative to `now_ms` when
+	/// `timeBuckets` is enabled; returns the flat list and non-empty buckets
+	/// in [`TIME_BUCKETS`] order for the result.
+	fn bucket_matches(
+		&self,
+		matches: Vec<GlobMatch>,
+		now_ms: f64,
+	) -> (Vec<GlobMatch>, Option<Vec<GlobTimeBucket>>) {
+		if !self.time_buckets {
+			return (matches, None);
+		}
+		let mut buckets = TIME_BUCKETS
+			.map(|(bucket, _)| GlobTimeBucket { bucket: bucket.to_string(), matches: Vec::new() });
+		for entry in matches {
+			let age_ms = entry.mtime.map_or(f64::INFINITY, |mtime| now_ms - mtime);
+			let index = TIME_BUCKETS
+				.iter()
+				.position(|&(_, max_age_ms)| age_ms < max_age_ms)
+				.unwrap_or(TIME_BUCKETS.len() - 1);
+			buckets[index].matches.push(entry);
+		}
+		let buckets = buckets
+			.into_iter()
+			.filter(|bucket| !bucket.matches.is_empty())
+			.collect();
+		(Vec::new(), Some(buckets))
+	}
+
+	/// Moves `matches` into a tree rooted at the search root when `asTree` is
+	/// enabled; returns the flat list and tree for the result.
+	fn tree_matches(&self, matches: Vec<GlobMatch>) -> (Vec<GlobMatch>, Option<GlobTreeNode>) {
+		if !self.as_tree {
+			return (matches, None);
+		}
+		// Nodes live in a flat arena while building; `children` holds indices.
+		let mut nodes = vec![(GlobTreeNode::dir(""), Vec::<usize>::new())];
+		let mut index_by_path: HashMap<String, usize> = HashMap::new();
+		for entry in matches {
+			let mut parent = 0;
+			let mut end = 0;
+			for component in entry.path.split('/') {
+				end += component.len();
+				let path = &entry.path[..end];
+				end += 1;
+				parent = if let Some(&index) = index_by_path.get(path) {
+					index
+				} else {
+					nodes.push((GlobTreeNode::dir(path), Vec::new()));
+					let index = nodes.len() - 1;
+					nodes[parent].1.push(index);
+					index_by_path.insert(path.to_string(), index);
+					index
+				};
+			}
+			let node = &mut nodes[parent].0;
+			node.file_type = entry.file_type;
+			node.matched = true;
+		}
+		(Vec::new(), Some(GlobTreeNode::assemble(&mut nodes, 0)))
+	}
+}
+
+impl GlobTreeNode {
+	/// An unmatched directory node at `path`.
+	fn dir(path: &str) -> Self {
+		let name = entry_name(path);
+		Self {
+			name:      name.to_string(),
+			path:      path.to_string(),
+			file_type: FileType::Dir,
+			matched:   false,
+			children:  Vec::new(),
+		}
+	}
+
+	/// Calls `visit` on this node and every descendant.
+	fn visit_mut(&mut self, visit: &mut impl FnMut(&mut Self)) {
+		visit(self);
+		for child in &mut self.children {
+			child.visit_mut(visit);
+		}
+	}
+
+	/// Takes node `index` out of the arena with its children attached.
+	fn assemble(nodes: &mut [(Self, Vec<usize>)], index: usize) -> Self {
+		let child_indices = std::mem::take(&mut nodes[index].1);
+		let children = child_indices
+			.into_iter()
+			.map(|child| Self::assemble(nodes, child))
+			.collect();
+		let mut node = std::mem::replace(&mut nodes[index].0, Self::dir(""));
+		node.children = children;
+		node
+	}
+}
+
+/// Whether `entries[index]` is a directory with no descendants in the scan.
+///
+/// Relies on walker order: descendants immediately follow their directory.
+fn is_empty_dir(entries: &[GlobMatch], index: usize) -> bool {
+	let dir = &entries[index];
+	dir.file_type == FileType::Dir
+		&& entries.get(index + 1).is_none_or(|next| {
+			!next
+				.path
+				.strip_prefix(dir.path.as_str())
+				.is_some_and(|rest| rest.starts_with('/'))
+		})
+}
+
+/// Number of directories between the root and `path` (0 = immediate child).
+fn entry_depth(path: &str) -> usize {
+	path.bytes().filter(|&byte| byte == b'/').count()
+}
+
+/// Entries accepted by [`filter_entries`].
+#[derive(Default)]
+struct FilterOutcome {
+	/// Collected matches (empty in stream-only mode).
+	matches:            Vec<GlobMatch>,
+	/// Number of accepted entries, including ones not collected.
+	matched:            usize,
+	/// Number of otherwise-accepted entries rejected for path length.
+	skipped_long_paths: usize,
+	/// Whether collection stopped at the result byte budget.
+	truncated:          bool,
+	/// Whether collection stopped at a partial-mode timeout.
+	timed_out:          bool,
+	/// Per-extension counts when `extensionBreakdown` is set.
+	extensions:         Option<HashMap<String, ExtensionCounts>>,
+}
+
+/// A match in a bounded `sortByMtime` heap; greater means later in the
+/// results, so the heap's max is the first candidate to drop.
+struct MtimeRanked {
+	entry:     GlobMatch,
+	ascending: bool,
+}
+
+impl Ord for MtimeRanked {
+	fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+		mtime_order(&self.entry, &other.entry, self.ascending)
+	}
+}
+
+impl PartialOrd for MtimeRanked {
+	fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl PartialEq for MtimeRanked {
+	fn eq(&self, other: &Self) -> bool {
+		self.cmp(other).is_eq()
+	}
+}
+
+impl Eq for MtimeRanked {}
+
+/// `sortByMtime` result order: by mtime (newest first unless `ascending`),
+/// ties broken by path.
+fn mtime_order(a: &GlobMatch, b: &GlobMatch, ascending: bool) -> std::cmp::Ordering {
+	let a_mtime = a.mtime.unwrap_or(0.0);
+	let b_mtime = b.mtime.unwrap_or(0.0);
+	let ordering = a_mtime
+		.partial_cmp(&b_mtime)
+		.unwrap_or(std::cmp::Ordering::Equal)
+		.then_with(|| a.path.cmp(&b.path));
+	if ascending {
+		ordering
+	} else {
+		ordering.reverse()
+	}
+}
+
+/// Collects every ancestor directory of the files in `entries` that match
+/// `glob_set`.
+fn dirs_with_matching_files<'a>(
+	entries: &'a [GlobMatch],
+	glob_set: &GlobSet,
+	config: &GlobConfig,
+	ct: &task::CancelToken,
+) -> Result<HashSet<&'a str>> {
+	let mut dirs = HashSet::new();
+	for entry in entries {
+		ct.heartbeat()?;
+		if entry.file_type != FileType::File
+			|| fs_cache::should_skip_path(Path::new(&entry.path), config.mentions_node_modules)
+			|| !glob_set.is_match(&entry.path)
+		{
+			continue;
+		}
+		let mut path = entry.path.as_str();
+		while let Some((parent, _)) = path.rsplit_once('/') {
+			if !dirs.insert(parent) {
+				// Remaining ancestors were recorded by an earlier match.
+				break;
+			}
+			path = parent;
+		}
+	}
+	Ok(dirs)
+}
+
+/// Replaces crowded subtrees of `matches` with one directory entry each.
+///
+/// Works bottom-up: a directory collapses when more than `threshold` result
+/// entries (counting already-collapsed subdirectories as one) would remain
+/// under it. The collapsed entry takes the position of its first match and
+/// records the number of matches it stands for.
+fn collapse_matches(matches: Vec<GlobMatch>, threshold: usize) -> Vec<GlobMatch> {
+	let mut under: HashMap<&str, usize> = HashMap::new();
+	for entry in &matches {
+		let mut path = entry.path.as_str();
+		while let Some((parent, _)) = path.rsplit_once('/') {
+			*under.entry(parent).or_default() += 1;
+			path = parent;
+		}
+	}
+
+	// Deepest first, so a collapsed subtree counts once toward its ancestors.
+	let mut dirs: Vec<&str> = under.keys().copied().collect();
+	dirs.sort_by_key(|dir| std::cmp::Reverse(dir.matches('/').count()));
+	let mut visible = under.clone();
+	let mut collapsed: HashMap<String, usize> = HashMap::new();
+	for dir in dirs {
+		let count = visible[dir];
+		if count <= threshold {
+			continue;
+		}
+		collapsed.insert(dir.to_string(), under[dir]);
+		let mut path = dir;
+		while let Some((parent, _)) = path.rsplit_once('/') {
+			if let Some(ancestor) = visible.get_mut(parent) {
+				*ancestor -= count - 1;
+			}
+			path = parent;
+		}
+	}
+	if collapsed.is_empty() {
+		return matches;
+	}
+
+	let mut emitted: HashSet<String> = HashSet::new();
+	let mut result = Vec::with_capacity(matches.len());
+	for entry in matches {
+		// The outermost collapsed directory containing (or equal to) this entry.
+		let dir = entry
+			.path
+			.match_indices('/')
+			.map(|(index, _)| &entry.path[..index])
+			.chain(std::iter::once(entry.path.as_str()))
+			.find(|prefix| collapsed.contains_key(*prefix));
+		let Some(dir) = dir else {
+			result.push(entry);
+			continue;
+		};
+		if emitted.insert(dir.to_string()) {
+			let mut summary = GlobMatch::new(dir.to_string(), FileType::Dir, None);
+			summary.collapsed_count = Some(collapsed[dir].min(u32::MAX as usize) as u32);
+			result.push(summary);
+		}
+	}
+	result
+}
+
+/// Absolute, symlink-resolved form of `path` (relative to `root`) for
+/// `matchResolvedPath`, with `/` separators. Falls back to the unresolved path
+/// when it can't be canonicalized, e.g. a dangling symlink.
+fn resolved_match_path(root: &Path, path: &str) -> String {
+	let joined = root.join(path);
+	let resolved = std::fs::canonicalize(&joined).unwrap_or(joined);
+	let resolved = resolved.to_string_lossy();
+	if cfg!(windows) {
+		resolved.trim_start_matches(r"\\?\").replace('\\', "/")
+	} else {
+		resolved.into_owned()
+	}
+}
+
+/// File type sniffed for `contentType`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ContentType {
+	Image,
+	Archive,
+	Pdf,
+	Text,
+	/// Anything but text; only ever wanted, never detected.
+	Binary,
+}
+
+/// Header signatures for `contentType` sniffing: byte offset, magic bytes,
+/// and detected type.
+const CONTENT_SIGNATURES: &[(usize, &[u8], ContentType)] = &[
+	(0, b"\x89PNG\r\n\x1a\n", ContentType::Image),
+	(0, b"\xff\xd8\xff", ContentType::Image),
+	(0, b"GIF87a", ContentType::Image),
+	(0, b"GIF89a", ContentType::Image),
+	(8, b"WEBP", ContentType::Image),
+	(0, b"\x00\x00\x01\x00", ContentType::Image),
+	(0, b"PK\x03\x04", ContentType::Archive),
+	(0, b"\x1f\x8b", ContentType::Archive),
+	(0, b"\xfd7zXZ\x00", ContentType::Archive),
+	(0, b"7z\xbc\xaf\x27\x1c", ContentType::Archive),
+	(0, b"\x28\xb5\x2f\xfd", ContentType::Archive),
+	(0, b"%PDF-", ContentType::Pdf),
+];
+
+/// Type of the file at `path` judged from its first 512 bytes: a
+/// [`CONTENT_SIGNATURES`] match, else text for NUL-free UTF-8 (including
+/// empty files), else binary. `None` when the file can't be read.
+fn sniff_content_type(path: &Path) -> Option<ContentType> {
+	let mut header = Vec::with_capacity(512);
+	std::fs::File::open(path)
+		.and_then(|file| file.take(512).read_to_end(&mut header))
+		.ok()?;
+	let signature = CONTENT_SIGNATURES.iter().find(|(offset, magic, _)| {
+		header
+			.get(*offset..offset + magic.len())
+			.is_some_and(|bytes| bytes == *magic)
+	});
+	if let Some(&(_, _, content_type)) = signature {
+		return Some(content_type);
+	}
+	// The header may end partway through a multi-byte character.
+	let utf8 = std::str::from_utf8(&header)
+		.err()
+		.is_none_or(|err| err.error_len().is_none());
+	Some(if utf8 && !header.contains(&0) {
+		ContentType::Text
+	} else {
+		ContentType::Binary
+	})
+}
+
+/// Whether the file at `path` sniffs as `wanted`; binary takes any non-text
+/// type.
+fn content_type_matches(path: &Path, wanted: ContentType) -> bool {
+	sniff_content_type(path).is_some_and(|detected| {
+		detected == wanted || (wanted == ContentType::Binary && detected != ContentType::Text)
+	})
+}
+
+/// `path` canonicalized for `realPath`, without the Windows verbatim prefix.
+fn real_path(path: &Path) -> Option<String> {
+	let resolved = std::fs::canonicalize(path).ok()?;
+	let resolved = resolved.to_string_lossy();
+	Some(
+		resolved
+			.strip_prefix(r"\\?\")
+			.unwrap_or(&resolved)
+			.to_string(),
+	)
+}
+
+/// Ask the `filter` predicate whether to keep `entry`, waiting on the JS
+/// thread while still honoring cancellation. Errors and non-boolean returns
+/// drop the entry.
+fn filter_keeps(filter: &GlobFilter, entry: &GlobMatch, ct: &task::CancelToken) -> bool {
+	let (tx, rx) = std::sync::mpsc::sync_channel(1);
+	filter.call_with_return_value(
+		entry.clone(),
+		ThreadsafeFunctionCallMode::NonBlocking,
+		move |keep, _env| {
+			let _ = tx.send(keep.unwrap_or(false));
+			Ok(())
+		},
+	);
+	loop {
+		match rx.recv_timeout(Duration::from_millis(50)) {
+			Ok(keep) => return keep,
+			Err(std::sync::mpsc::RecvTimeoutError::Timeout) if ct.heartbeat().is_ok() => {},
+			Err(_) => return false,
+		}
+	}
 }
 
 /// Filter and collect matching entries from a pre-scanned list.
+///
+/// When `git_paths` is set, only entries in it are accepted (see
+/// [`git_filter_paths`]). `root` is the directory entry paths are relative to,
+/// needed for `matchResolvedPath`, `brokenSymlinksOnly`, and `canonicalize`.
 fn filter_entries(
 	entries: &[GlobMatch],
 	glob_set: &GlobSet,
 	config: &GlobConfig,
+	root: Option<&Path>,
+	git_paths: Option<&HashSet<String>>,
 	on_match: Option<&ThreadsafeFunction<GlobMatch>>,
 	ct: &task::CancelToken,
-) -> Result<Vec<GlobMatch>> {
-	let mut matches = Vec::new();
+) -> Result<FilterOutcome> {
+	let mut outcome = FilterOutcome::default();
 	if config.max_results == 0 {
-		return Ok(matches);
+		return Ok(outcome);
 	}
+	// Reordering needs every candidate; the budget is then applied afterwards.
+	let byte_budget = config
+		.max_result_bytes
+		.filter(|_| !config.defers_truncation() && !config.stream_only);
+	let mut result_bytes = 0usize;
+	let mut per_dir_counts: HashMap<&str, usize> = HashMap::new();
+	// Reordering picks the first of each basename after sorting instead.
+	let dedup_basenames = config.unique_by_basename && !config.defers_truncation();
+	let mut seen_basenames = HashSet::new();
+	let name_regex = config
+		.name_regex
+		.as_deref()
+		.map(compile_name_regex)
+		.transpose()?;
 
-	for entry in entries {
-		ct.heartbeat()?;
+	let match_dirs = if config.dirs_with_matches {
+		Some(dirs_with_matching_files(entries, glob_set, config, ct)?)
+	} else {
+		None
+	};
+	let child_counts = config
+		.include_child_count
+		.then(|| count_children(entries, config));
+	let mut last_callback: Option<Instant> = None;
+	// Keeps only the best `max_results` candidates: O(n log k) instead of
+	// sorting every match.
+	let mut top_k = config.mtime_top_k().then(BinaryHeap::new);
+	let mut extensions: Option<HashMap<&str, ExtensionCounts>> =
+		config.extension_breakdown.then(HashMap::new);
+	let resolve_root = root.filter(|_| config.match_resolved_path);
+	let broken_link_root = root.filter(|_| config.broken_symlinks_only);
+	let sniff_root = root.filter(|_| config.content_type.is_some());
+	// `globMatchEntries` runs on the JS thread, where waiting on the
+	// predicate would deadlock.
+	let filter = config.filter.as_ref().filter(|_| root.is_some());
+
+	for (index, entry) in entries.iter().enumerate() {
+		if let Err(err) = ct.heartbeat() {
+			if config.is_partial_timeout(&err) {
+				outcome.timed_out = true;
+				break;
+			}
+			return Err(err);
+		}
 		if fs_cache::should_skip_path(Path::new(&entry.path), config.mentions_node_modules) {
 			// Apply post-scan node_modules policy before glob matching.
 			continue;
 		}
-		if !glob_set.is_match(&entry.path) {
+		if let Some(counts) = &mut extensions
+			&& entry.file_type != FileType::Dir
+		{
+			counts
+				.entry(entry_extension(&entry.path))
+				.or_default()
+				.scanned += 1;
+		}
+		let match_path = match (resolve_root, &match_dirs, &config.fuzzy) {
+			(Some(root), None, None) => Cow::Owned(resolved_match_path(root, &entry.path)),
+			_ => Cow::Borrowed(entry.path.as_str()),
+		};
+		let mut score = None;
+		let accepted = match (&match_dirs, &config.fuzzy) {
+			(Some(dirs), _) => entry.file_type == FileType::Dir && dirs.contains(entry.path.as_str()),
+			(None, Some(query)) => {
+				score = Some(query.score(entry));
+				score != Some(0)
+			},
+			(None, None) => glob_set.is_match(&*match_path),
+		};
+		if !accepted {
+			continue;
+		}
+		if let Some(regex) = &name_regex
+			&& !regex
+				.is_match(entry_name(&entry.path).as_bytes())
+				.unwrap_or(false)
+		{
+			continue;
+		}
+		if !config.file_type_filter.is_empty() && !config.file_type_filter.contains(&entry.file_type)
+		{
+			continue;
+		}
+		if config.dirs_only && entry.file_type != FileType::Dir {
+			continue;
+		}
+		// Scanned types come from `symlink_metadata`; following the link tells
+		// whether its target exists.
+		if let Some(root) = broken_link_root
+			&& (entry.file_type != FileType::Symlink
+				|| std::fs::metadata(root.join(&entry.path)).is_ok())
+		{
+			continue;
+		}
+		if git_paths.is_some_and(|paths| !paths.contains(&entry.path)) {
+			continue;
+		}
+		if config
+			.exact_depth
+			.is_some_and(|depth| entry_depth(&entry.path) != depth)
+		{
+			continue;
+		}
+		if config
+			.newer_than_ms
+			.is_some_and(|threshold| entry.mtime.is_none_or(|mtime| mtime < threshold))
+		{
+			continue;
+		}
+		if config
+			.language_filter
+			.as_ref()
+			.is_some_and(|filter| !grep::matches_type_filter(Path::new(&entry.path), filter))
+		{
+			continue;
+		}
+		if config.empty_dirs_only && !is_empty_dir(entries, index) {
+			continue;
+		}
+		if let Some(root) = sniff_root
+			&& let Some(wanted) = config.content_type
+			&& (entry.file_type != FileType::File
+				|| !content_type_matches(&root.join(&entry.path), wanted))
+		{
 			continue;
 		}
 		if config
-			.file_type_filter
-			.is_some_and(|filter| filter != entry.file_type)
+			.max_path_length
+			.is_some_and(|max| entry.path.chars().count() > max)
+		{
+			outcome.skipped_long_paths += 1;
+			continue;
+		}
+		if let Some(filter) = filter
+			&& !filter_keeps(filter, entry, ct)
 		{
 			continue;
 		}
+		if let Some(quota) = config.max_results_per_dir {
+			let dir = entry.path.rsplit_once('/').map_or("", |(dir, _)| dir);
+			let count = per_dir_counts.entry(dir).or_default();
+			if *count >= quota {
+				continue;
+			}
+			*count += 1;
+		}
+		if dedup_basenames && !seen_basenames.insert(entry_name(&entry.path)) {
+			continue;
+		}
+		if let Some(budget) = byte_budget {
+			result_bytes += entry.path.len();
+			if result_bytes > budget {
+				outcome.truncated = true;
+				break;
+			}
+		}
+		let mut entry = entry.clone();
+		entry.score = score;
+		if let Some(counts) = &child_counts
+			&& entry.file_type == FileType::Dir
+		{
+			entry.child_count = Some(counts.get(entry.path.as_str()).copied().unwrap_or(0));
+		}
+		if config.include_order_index {
+			entry.order_index = Some(index.min(u32::MAX as usize) as u32);
+		}
+		if config.include_depth {
+			entry.depth = Some(entry_depth(&entry.path).min(u32::MAX as usize) as u32);
+		}
+		if config.include_pattern_index {
+			// The only pattern; see `includeMatchedPatternIndex`.
+			entry.pattern_index = Some(0);
+		}
+		if config.canonicalize
+			&& let Some(root) = root
+		{
+			entry.real_path = real_path(&root.join(&entry.path));
+		}
 		if let Some(callback) = on_match {
-			callback.call(Ok(entry.clone()), ThreadsafeFunctionCallMode::NonBlocking);
+			let throttled = config
+				.callback_interval
+				.zip(last_callback)
+				.is_some_and(|(interval, last)| last.elapsed() < interval);
+			if !throttled {
+				last_callback = Some(Instant::now());
+				let mut streamed = entry.clone();
+				config.output_path(&mut streamed.path);
+				callback.call(Ok(streamed), ThreadsafeFunctionCallMode::NonBlocking);
+			}
 		}
 
-		matches.push(entry.clone());
-		// Only early-break when not sorting; mtime sort requires full candidate set.
-		if !config.sort_by_mtime && matches.len() >= config.max_results {
+		if let Some(counts) = &mut extensions
+			&& entry.file_type != FileType::Dir
+			&& let Some(counts) = counts.get_mut(entry_extension(&entry.path))
+		{
+			counts.matched += 1;
+		}
+		outcome.matched += 1;
+		if let Some(heap) = &mut top_k {
+			heap.push(MtimeRanked { entry, ascending: config.sort_ascending });
+			if heap.len() > config.max_results {
+				heap.pop();
+			}
+		} else if !config.stream_only {
+			outcome.matches.push(entry);
+		}
+		// Only early-break when not sorting; reordering requires full candidate set.
+		if !config.defers_truncation() && outcome.matched >= config.max_results {
 			break;
 		}
 	}
-	Ok(matches)
+	if let Some(heap) = top_k {
+		outcome.matches = heap
+			.into_sorted_vec()
+			.into_iter()
+			.map(|ranked| ranked.entry)
+			.collect();
+	}
+	outcome.extensions = extensions.map(|counts| {
+		counts
+			.into_iter()
+			.map(|(extension, counts)| (extension.to_string(), counts))
+			.collect()
+	});
+	Ok(outcome)
 }
 
-/// Executes matching/filtering over scanned entries and optionally streams each
-/// hit.
-fn run_glob(
-	config: GlobConfig,
-	on_match: Option<&ThreadsafeFunction<GlobMatch>>,
-	ct: task::CancelToken,
-) -> Result<GlobResult> {
-	let glob_set = compile_glob(&config.pattern)?;
-	if config.max_results == 0 {
-		return Ok(GlobResult { matches: Vec::new(), total_matches: 0 });
+/// Number of visible `entries` directly under each directory path; top-level
+/// entries count under `""`.
+fn count_children<'a>(entries: &'a [GlobMatch], config: &GlobConfig) -> HashMap<&'a str, u32> {
+	let mut counts = HashMap::new();
+	for entry in entries {
+		if fs_cache::should_skip_path(Path::new(&entry.path), config.mentions_node_modules) {
+			continue;
+		}
+		let parent = entry.path.rsplit_once('/').map_or("", |(parent, _)| parent);
+		*counts.entry(parent).or_default() += 1;
 	}
+	counts
+}
 
-	let mut matches = if config.use_cache {
-		let scan =
-			fs_cache::get_or_scan(&config.root, config.include_hidden, config.use_gitignore, &ct)?;
-		let mut matches = filter_entries(&scan.entries, &glob_set, &config, on_match, &ct)?;
-		// Empty-result recheck: if we got zero matches from a cached scan that's old
-		// enough, force a rescan and try once more before returning empty.
-		if matches.is_empty() && scan.cache_age_ms >= fs_cache::empty_recheck_ms() {
-			let fresh = fs_cache::force_rescan(
-				&config.root,
-				config.include_hidden,
-				config.use_gitignore,
-				true,
-				&ct,
-			)?;
-			matches = filter_entries(&fresh, &glob_set, &config, on_match, &ct)?;
-		}
-		matches
-	} else {
-		let fresh = fs_cache::force_rescan(
-			&config.root,
-			config.include_hidden,
-			config.use_gitignore,
-			false,
-			&ct,
-		)?;
-		filter_entries(&fresh, &glob_set, &config, on_match, &ct)?
+/// Extension of a relative match path, without the dot; `""` when it has
+/// none.
+fn entry_extension(path: &str) -> &str {
+	Path::new(path)
+		.extension()
+		.and_then(|extension| extension.to_str())
+		.unwrap_or("")
+}
+
+/// Final component of a relative match path.
+fn entry_name(path: &str) -> &str {
+	path.rsplit('/').next().unwrap_or(path)
+}
+
+fn compile_name_regex(pattern: &str) -> Result<RegexMatcher> {
+	RegexMatcher::new(pattern)
+		.map_err(|err| error::coded(ErrorCode::InvalidPattern, format!("Invalid nameRegex: {err}")))
+}
+
+fn has_glob_meta(component: &str) -> bool {
+	component.contains(['*', '?', '[', '{'])
+}
+
+/// Runs `git -C root <args>`.
+fn git(root: &Path, args: &[&str]) -> Result<std::process::Output> {
+	std::process::Command::new("git")
+		.arg("-C")
+		.arg(root)
+		.args(args)
+		.output()
+		.map_err(|err| error::coded(ErrorCode::Io, format!("Failed to run git: {err}")))
+}
+
+/// Stdout of a git command that must succeed.
+fn git_stdout(root: &Path, args: &[&str]) -> Result<String> {
+	let output = git(root, args)?;
+	if !output.status.success() {
+		let stderr = String::from_utf8_lossy(&output.stderr);
+		return Err(error::coded(
+			ErrorCode::Io,
+			format!("git {} failed: {}", args[0], stderr.trim()),
+		));
+	}
+	Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Path of `root` below the repository root (empty or ending in `/`).
+fn git_prefix(root: &Path) -> Result<String> {
+	let output = git(root, &["rev-parse", "--show-prefix"])?;
+	if !output.status.success() {
+		return Err(error::coded(
+			ErrorCode::NotAGitRepo,
+			format!("Not a git repository: {}", root.display()),
+		));
+	}
+	Ok(String::from_utf8_lossy(&output.stdout)
+		.trim_end()
+		.to_string())
+}
+
+/// Paths under `root` that `git status` reports as modified, staged, or
+/// untracked, relative to `root` with `/` separators.
+///
+/// Untracked directories are expanded to their files; deleted files are
+/// listed but never match a scanned entry.
+fn git_changed_paths(root: &Path) -> Result<HashSet<String>> {
+	// Porcelain paths are relative to the repository root, not to `root`.
+	let prefix = git_prefix(root)?;
+	let status = git_stdout(root, &[
+		"status",
+		"--porcelain=v1",
+		"-z",
+		"--no-renames",
+		"--untracked-files=all",
+		"--",
+		".",
+	])?;
+	Ok(status
+		.split('\0')
+		// Each record is `XY <path>`.
+		.filter_map(|record| record.get(3..))
+		.filter_map(|path| path.strip_prefix(prefix.as_str()))
+		.map(|path| path.trim_end_matches('/').to_string())
+		.collect())
+}
+
+/// Paths under `root` that git tracks, relative to `root` with `/`
+/// separators.
+fn git_tracked_paths(root: &Path) -> Result<HashSet<String>> {
+	// Fails with `NOT_A_GIT_REPO` before `ls-files` reports a generic error.
+	git_prefix(root)?;
+	let files = git_stdout(root, &["ls-files", "-z", "--", "."])?;
+	Ok(files
+		.split('\0')
+		.filter(|path| !path.is_empty())
+		.map(str::to_string)
+		.collect())
+}
+
+/// Paths allowed by the git filters (`onlyModifiedInGit`, `onlyGitTracked`)
+/// under `root`; `None` when neither is set.
+fn git_filter_paths(root: &Path, config: &GlobConfig) -> Result<Option<HashSet<String>>> {
+	let changed = config
+		.only_modified_in_git
+		.then(|| git_changed_paths(root))
+		.transpose()?;
+	let tracked = config
+		.only_git_tracked
+		.then(|| git_tracked_paths(root))
+		.transpose()?;
+	Ok(match (changed, tracked) {
+		(Some(changed), Some(tracked)) => Some(
+			changed
+				.into_iter()
+				.filter(|path| tracked.contains(path))
+				.collect(),
+		),
+		(changed, tracked) => changed.or(tracked),
+	})
+}
+
+/// Adds entries selected by `force_include` that the scan left out.
+///
+/// Literal paths are classified directly; globs walk their literal base
+/// directory with ignore rules disabled. The merged list is re-sorted into
+/// walker order.
+fn inject_forced(
+	root: &Path,
+	config: &GlobConfig,
+	mut entries: Vec<GlobMatch>,
+	ct: &task::CancelToken,
+) -> Result<Vec<GlobMatch>> {
+	if config.force_include.is_empty() {
+		return Ok(entries);
+	}
+
+	let mut seen: HashSet<String> = entries.iter().map(|entry| entry.path.clone()).collect();
+	let mut push_forced = |entries: &mut Vec<GlobMatch>, path: &Path| {
+		let relative = fs_cache::normalize_relative_path(root, path);
+		if relative.is_empty() || seen.contains(relative.as_ref()) {
+			return;
+		}
+		if let Some((file_type, mtime)) = fs_cache::classify_file_type(path) {
+			seen.insert(relative.to_string());
+			entries.push(GlobMatch::new(relative.into_owned(), file_type, mtime));
+		}
 	};
 
-	if config.sort_by_mtime {
-		// Sorting mode: rank by mtime descending, then apply max-results truncation.
-		matches.sort_by(|a, b| {
-			let a_mtime = a.mtime.unwrap_or(0.0);
-			let b_mtime = b.mtime.unwrap_or(0.0);
-			b_mtime
-				.partial_cmp(&a_mtime)
-				.unwrap_or(std::cmp::Ordering::Equal)
+	let scanned = entries.len();
+	for pattern in &config.force_include {
+		ct.heartbeat()?;
+		let pattern = pattern.trim_start_matches("./");
+		if !has_glob_meta(pattern) {
+			push_forced(&mut entries, &root.join(pattern));
+			continue;
+		}
+
+		let glob_set = compile_glob(
+			pattern,
+			config.normalize_separators,
+			config.literal_separator,
+			config.auto_recursive,
+		)?;
+		let base: PathBuf = pattern
+			.split('/')
+			.take_while(|component| !has_glob_meta(component))
+			.fold(root.to_path_buf(), |base, component| base.join(component));
+		let options =
+			fs_cache::ScanOptions { use_gitignore: false, use_piignore: false, ..config.scan_options };
+		for walked in fs_cache::build_walker(&base, options).build() {
+			ct.heartbeat()?;
+			let Ok(walked) = walked else { continue };
+			let path = walked.path();
+			if fs_cache::should_skip_path(path, true)
+				|| !glob_set.is_match(fs_cache::normalize_relative_path(root, path).as_ref())
+			{
+				continue;
+			}
+			push_forced(&mut entries, path);
+		}
+	}
+
+	if entries.len() > scanned {
+		entries.sort_by(|a, b| Path::new(&a.path).cmp(Path::new(&b.path)));
+	}
+	Ok(entries)
+}
+
+/// Executes matching/filtering over scanned entries and optionally streams each
+/// hit.
+fn run_glob(
+	config: GlobConfig,
+	glob_set: &GlobSet,
+	on_match: Option<&ThreadsafeFunction<GlobMatch>>,
+	ct: task::CancelToken,
+) -> Result<GlobResult> {
+	// Taken before scanning so changes made mid-scan are newer than the snapshot.
+	let started_ms = now_ms() - SNAPSHOT_SLACK_MS;
+	let root = if config.root_already_resolved && Path::new(&config.path).is_absolute() {
+		// The caller vouches for the path; a bad one just walks nothing.
+		PathBuf::from(&config.path)
+	} else {
+		match fs_cache::resolve_search_path(&config.path) {
+			Err(err)
+				if config.allow_file_root && error::code_of(&err) == Some(ErrorCode::NotADirectory) =>
+			{
+				return glob_file_root(&config, glob_set, on_match, &ct, started_ms);
+			},
+			root => root?,
+		}
+	};
+	let git_paths = git_filter_paths(&root, &config)?;
+	if config.max_results == 0 {
+		let result_hash = config.result_hash(&[]);
+		let (matches, paths) = config.format_matches(Vec::new());
+		let (matches, tree) = config.tree_matches(matches);
+		let (matches, groups) = config.group_matches(matches);
+		let (matches, time_buckets) = config.bucket_matches(matches, now_ms());
+		let (matches, buffer) = config.encode_matches(matches);
+		return Ok(GlobResult {
+			matches,
+			total_matches: 0,
+			rescan_skipped_due_to_budget: false,
+			scan_capped: false,
+			symlink_loops_detected: 0,
+			gitignore_applied: false,
+			from_cache: false,
+			skipped_long_paths: 0,
+			truncated: false,
+			snapshot_ms: config.return_snapshot.then_some(started_ms),
+			groups,
+			tree,
+			timed_out: false,
+			result_hash,
+			paths,
+			extension_breakdown: None,
+			time_buckets,
+			buffer,
 		});
+	}
+
+	let mut scan_capped = false;
+	let mut symlink_loops_detected = 0;
+	let mut gitignore_applied = false;
+	let mut scan_age_ms = 0;
+	let mut from_cache = false;
+	let mut select = |scan: fs_cache::ScanResult| {
+		scan_capped = scan.capped;
+		symlink_loops_detected = scan.symlink_loops;
+		gitignore_applied = scan.gitignore_applied;
+		scan_age_ms = scan.cache_age_ms;
+		from_cache = scan.from_cache;
+		let entries = inject_forced(&root, &config, scan.entries, &ct)?;
+		filter_entries(&entries, glob_set, &config, Some(&root), git_paths.as_ref(), on_match, &ct)
+	};
+
+	let report_progress = |discovered: usize| {
+		if let Some(callback) = &config.scan_progress
+			&& !ct.aborted()
+		{
+			let discovered = u32::try_from(discovered).unwrap_or(u32::MAX);
+			callback.call(Ok(discovered), ThreadsafeFunctionCallMode::NonBlocking);
+		}
+	};
+	let progress = config
+		.scan_progress
+		.is_some()
+		.then_some(&report_progress as fs_cache::ScanProgress<'_>);
+
+	let mut rescan_skipped_due_to_budget = false;
+	let scanned = (|| {
+		if !config.use_cache {
+			let fresh = fs_cache::force_rescan(
+				&root,
+				config.scan_options,
+				&config.reinclude,
+				&config.cache_namespace,
+				config.populate_cache,
+				progress,
+				&ct,
+			)?;
+			return select(fresh);
+		}
+		let scan = fs_cache::get_or_scan(
+			&root,
+			config.scan_options,
+			&config.reinclude,
+			&config.cache_namespace,
+			progress,
+			&ct,
+		)?;
+		let cache_age_ms = scan.cache_age_ms;
+		let mut outcome = select(scan)?;
+		// Empty-result recheck: if we got zero matches from a cached scan that's old
+		// enough, force a rescan and try once more before returning empty.
+		if outcome.matched == 0 && !outcome.timed_out && cache_age_ms >= fs_cache::empty_recheck_ms()
+		{
+			if ct
+				.remaining_ms()
+				.is_some_and(|remaining| remaining < MIN_RESCAN_BUDGET_MS)
+			{
+				// A rescan would likely blow the timeout; return the cached empty set.
+				rescan_skipped_due_to_budget = true;
+			} else {
+				let fresh = fs_cache::force_rescan(
+					&root,
+					config.scan_options,
+					&config.reinclude,
+					&config.cache_namespace,
+					true,
+					progress,
+					&ct,
+				)?;
+				outcome = select(fresh)?;
+			}
+		}
+		Ok(outcome)
+	})();
+	let mut outcome = match scanned {
+		// Timed out before filtering started (e.g. while scanning): nothing found yet.
+		Err(err) if config.is_partial_timeout(&err) => {
+			FilterOutcome { timed_out: true, ..Default::default() }
+		},
+		scanned => scanned?,
+	};
+
+	order_matches(&config, &mut outcome);
+	let FilterOutcome { matches, matched, skipped_long_paths, truncated, timed_out, extensions } =
+		outcome;
+	config.require_match(matched, timed_out)?;
+	let total_matches = matched.min(config.max_results).min(u32::MAX as usize) as u32;
+	let result_hash = config.result_hash(&matches);
+	let (matches, paths) = config.format_matches(matches);
+	let (matches, tree) = config.tree_matches(matches);
+	let (matches, groups) = config.group_matches(matches);
+	let (matches, time_buckets) = config.bucket_matches(matches, now_ms());
+	let (matches, buffer) = config.encode_matches(matches);
+	Ok(config.output_result(GlobResult {
+		matches,
+		total_matches,
+		rescan_skipped_due_to_budget,
+		scan_capped,
+		symlink_loops_detected,
+		gitignore_applied,
+		from_cache,
+		skipped_long_paths: skipped_long_paths.min(u32::MAX as usize) as u32,
+		truncated,
+		// Cached entries reflect the filesystem as of when they were scanned.
+		snapshot_ms: config
+			.return_snapshot
+			.then_some(started_ms - scan_age_ms as f64),
+		groups,
+		tree,
+		timed_out,
+		result_hash,
+		paths,
+		extension_breakdown: extensions,
+		time_buckets,
+		buffer,
+	}))
+}
+
+/// Applies result ordering (`fuzzy` score or `sortByMtime`, then `dirsFirst`,
+/// then `priorityPaths`), the `topmostOnly` and `uniqueByBasename` dedup and
+/// `maxResults` and `maxResultBytes` truncation it defers, and
+/// `collapseThreshold` summarization.
+fn order_matches(config: &GlobConfig, outcome: &mut FilterOutcome) {
+	let matches = &mut outcome.matches;
+	if config.fuzzy.is_some() {
+		// Best score first; equal scores keep a stable path order.
+		matches.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.path.cmp(&b.path)));
+	} else if config.sort_by_mtime {
+		// Sorting mode: rank by mtime, then apply max-results truncation.
+		// Already sorted when filtering kept a top-K heap.
+		matches.sort_by(|a, b| mtime_order(a, b, config.sort_ascending));
+	}
+	if let Some(dirs_first) = config.dirs_first {
+		// Stable, so each group keeps the order above.
+		matches.sort_by_key(|entry| (entry.file_type == FileType::Dir) != dirs_first);
+	}
+	if !config.priority_paths.is_empty() {
+		// Stable sort keeps the existing order among non-priority matches.
+		let rank: HashMap<&str, usize> = config
+			.priority_paths
+			.iter()
+			.enumerate()
+			.map(|(index, path)| (path.as_str(), index))
+			.rev()
+			.collect();
+		matches.sort_by_key(|entry| rank.get(entry.path.as_str()).copied().unwrap_or(usize::MAX));
+	}
+	if config.defers_truncation() {
+		if config.topmost_only {
+			retain_topmost(matches);
+		}
+		if config.unique_by_basename {
+			let mut seen = HashSet::new();
+			matches.retain(|entry| seen.insert(entry_name(&entry.path).to_string()));
+		}
+		if (config.topmost_only || config.unique_by_basename) && !config.stream_only {
+			// Filtering counted the nested and duplicate matches dropped above.
+			outcome.matched = matches.len();
+		}
 		matches.truncate(config.max_results);
+		if let Some(budget) = config.max_result_bytes {
+			let mut result_bytes = 0usize;
+			let within = matches
+				.iter()
+				.take_while(|entry| {
+					result_bytes += entry.path.len();
+					result_bytes <= budget
+				})
+				.count();
+			if within < matches.len() {
+				matches.truncate(within);
+				outcome.truncated = true;
+			}
+		}
+	}
+	if let Some(threshold) = config.collapse_threshold {
+		*matches = collapse_matches(std::mem::take(matches), threshold);
 	}
-	let total_matches = matches.len().min(u32::MAX as usize) as u32;
-	Ok(GlobResult { matches, total_matches })
 }
 
-/// Find filesystem entries matching a glob pattern.
-///
-/// Resolves the search root, scans entries, applies glob and optional file-type
-/// filters, and optionally streams each accepted match through `on_match`.
+/// Drops matches below another matched directory, keeping the order of the
+/// rest.
+fn retain_topmost(matches: &mut Vec<GlobMatch>) {
+	let dirs: HashSet<String> = matches
+		.iter()
+		.filter(|entry| entry.file_type == FileType::Dir)
+		.map(|entry| entry.path.clone())
+		.collect();
+	matches.retain(|entry| {
+		!entry
+			.path
+			.match_indices('/')
+			.any(|(index, _)| dirs.contains(&entry.path[..index]))
+	});
+}
+
+/// Current wall-clock time in milliseconds since the Unix epoch.
+fn now_ms() -> f64 {
+	std::time::SystemTime::now()
+		.duration_since(std::time::UNIX_EPOCH)
+		.map_or(0.0, |elapsed| elapsed.as_millis() as f64)
+}
+
+/// Runs the filters against a single file passed as the search path.
 ///
-/// If `sortByMtime` is enabled, all matching entries are collected, sorted by
-/// descending mtime, then truncated to `maxResults`.
+/// The file is matched by its name, as if its parent directory were the root.
+fn glob_file_root(
+	config: &GlobConfig,
+	glob_set: &GlobSet,
+	on_match: Option<&ThreadsafeFunction<GlobMatch>>,
+	ct: &task::CancelToken,
+	started_ms: f64,
+) -> Result<GlobResult> {
+	let path = fs_cache::absolute_path(&config.path)?;
+	let name = path
+		.file_name()
+		.map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+	let entries: Vec<GlobMatch> = fs_cache::classify_file_type(&path)
+		.filter(|_| config.max_results > 0)
+		.map(|(file_type, mtime)| GlobMatch::new(name, file_type, mtime))
+		.into_iter()
+		.collect();
+	let git_paths = match path.parent() {
+		Some(parent) => git_filter_paths(parent, config)?,
+		None => None,
+	};
+	let mut outcome =
+		filter_entries(&entries, glob_set, config, path.parent(), git_paths.as_ref(), on_match, ct)?;
+	order_matches(config, &mut outcome);
+	let FilterOutcome { matches, matched, skipped_long_paths, truncated, timed_out, extensions } =
+		outcome;
+	config.require_match(matched, timed_out)?;
+	let result_hash = config.result_hash(&matches);
+	let (matches, paths) = config.format_matches(matches);
+	let (matches, tree) = config.tree_matches(matches);
+	let (matches, groups) = config.group_matches(matches);
+	let (matches, time_buckets) = config.bucket_matches(matches, now_ms());
+	let (matches, buffer) = config.encode_matches(matches);
+	Ok(config.output_result(GlobResult {
+		matches,
+		total_matches: matched.min(config.max_results).min(u32::MAX as usize) as u32,
+		rescan_skipped_due_to_budget: false,
+		scan_capped: false,
+		symlink_loops_detected: 0,
+		gitignore_applied: false,
+		from_cache: false,
+		skipped_long_paths: skipped_long_paths.min(u32::MAX as usize) as u32,
+		truncated,
+		snapshot_ms: config.return_snapshot.then_some(started_ms),
+		groups,
+		tree,
+		timed_out,
+		result_hash,
+		paths,
+		extension_breakdown: extensions,
+		time_buckets,
+		buffer,
+	}))
+}
+
+/// Normalizes a user pattern, treating blank input as `*`.
+fn effective_pattern(pattern: &str) -> String {
+	let pattern = pattern.trim();
+	let pattern = if pattern.is_empty() { "*" } else { pattern };
+	pattern.to_string()
+}
+
+/// Parses the string option `name` into the value paired with it in
+/// `choices`, or `default` when unset.
 ///
 /// # Errors
-/// Returns an error when the search path cannot be resolved, the path is not a
-/// directory, the glob pattern is invalid, or cancellation/timeout is
-/// triggered.
-#[napi(js_name = "glob")]
-pub fn glob(
-	options: GlobOptions<'_>,
-	#[napi(ts_arg_type = "((match: GlobMatch) => void) | undefined | null")] on_match: Option<
-		ThreadsafeFunction<GlobMatch>,
-	>,
-) -> task::Async<GlobResult> {
+/// Fails with `INVALID_PATTERN` for a value not in `choices`.
+fn parse_choice<T: Copy>(
+	name: &str,
+	value: Option<&str>,
+	default: T,
+	choices: &[(&str, T)],
+) -> Result<T> {
+	let Some(value) = value else {
+		return Ok(default);
+	};
+	choices
+		.iter()
+		.find(|(choice, _)| *choice == value)
+		.map(|&(_, parsed)| parsed)
+		.ok_or_else(|| {
+			let expected: Vec<&str> = choices.iter().map(|(choice, _)| *choice).collect();
+			error::coded(
+				ErrorCode::InvalidPattern,
+				format!("Unknown {name} {value:?}; expected one of: {}", expected.join(", ")),
+			)
+		})
+}
+
+/// Checks that each `forceInclude` glob starts with a literal directory.
+///
+/// [`inject_forced`] walks a glob's literal base with ignore rules off on
+/// every query, so a glob starting with a wildcard would walk the whole root.
+fn force_include_patterns(patterns: Option<Vec<String>>) -> Result<Vec<String>> {
+	let patterns = patterns.unwrap_or_default();
+	if let Some(pattern) = patterns.iter().find(|pattern| {
+		let first = pattern.trim_start_matches("./").split('/').next();
+		has_glob_meta(first.unwrap_or_default())
+	}) {
+		return Err(error::coded(
+			ErrorCode::InvalidPattern,
+			format!("forceInclude glob {pattern:?} must start with a literal directory"),
+		));
+	}
+	Ok(patterns)
+}
+
+/// Splits napi options into a runtime config and its cancel token.
+fn glob_config(options: GlobOptions<'_>) -> Result<(GlobConfig, task::CancelToken)> {
 	let GlobOptions {
 		pattern,
 		path,
 		file_type,
+		file_types,
 		hidden,
 		max_results,
 		gitignore,
 		sort_by_mtime,
+		sort_direction,
 		cache,
 		include_node_modules,
 		timeout_ms,
 		signal,
+		dedup_by_inode,
+		stream_only,
+		normalize_separators,
+		glob_syntax,
+		empty_dirs_only,
+		priority_paths,
+		populate_cache,
+		force_include,
+		max_scan_entries,
+		dirs_with_matches,
+		max_path_length,
+		exact_depth,
+		allow_file_root,
+		newer_than_snapshot_ms,
+		return_snapshot,
+		group_by_top_dir,
+		max_results_per_group,
+		max_results_per_dir,
+		unique_by_basename,
+		collapse_threshold,
+		max_result_bytes,
+		language,
+		language_extensions,
+		fuzzy,
+		as_tree,
+		timeout_behavior,
+		name_regex,
+		cache_namespace,
+		follow_symlinks,
+		max_symlink_depth,
+		parallel,
+		threads,
+		only_modified_in_git,
+		result_hash,
+		include_child_count,
+		callback_min_interval_ms,
+		include_order_index,
+		only_git_tracked,
+		auto_recursive,
+		format,
+		match_resolved_path,
+		broken_symlinks_only,
+		dirs_first,
+		extension_breakdown,
+		root_already_resolved,
+		include_discovered_at_ms,
+		on_scan_progress,
+		use_piignore,
+		native_separators,
+		time_buckets,
+		canonicalize,
+		require_match,
+		content_type,
+		topmost_only,
+		filter,
+		same_filesystem,
+		reinclude,
+		include_depth,
+		root_alias,
+		include_pattern_index,
 	} = options;
 
-	let pattern = pattern.trim();
-	let pattern = if pattern.is_empty() { "*" } else { pattern };
-	let pattern = pattern.to_string();
-
+	let pattern = effective_pattern(&pattern);
+	let time_buckets = time_buckets.unwrap_or(false);
+	let mut file_type_filter = file_types.unwrap_or_default();
+	file_type_filter.extend(file_type);
 	let ct = task::CancelToken::new(timeout_ms, signal);
+	let config = GlobConfig {
+		path,
+		scan_options: fs_cache::ScanOptions {
+			include_hidden: hidden.unwrap_or(false),
+			use_gitignore: gitignore.unwrap_or(true),
+			use_piignore: use_piignore.unwrap_or(true),
+			dedup_by_inode: dedup_by_inode.unwrap_or(false),
+			follow_links: follow_symlinks.unwrap_or(false),
+			max_symlink_depth,
+			same_file_system: same_filesystem.unwrap_or(false),
+			max_entries: max_scan_entries.map(|value| value as usize),
+			threads: parallel
+				.unwrap_or(false)
+				.then(|| threads.unwrap_or(0) as usize),
+			record_discovery: include_discovered_at_ms.unwrap_or(false),
+		},
+		file_type_filter,
+		dirs_only: is_dir_pattern(&pattern, normalize_separators.unwrap_or(false)),
+		max_results: max_results.map_or(usize::MAX, |value| value as usize),
+		mentions_node_modules: include_node_modules
+			.unwrap_or_else(|| pattern.contains("node_modules")),
+		sort_by_mtime: sort_by_mtime.unwrap_or(false) || time_buckets,
+		sort_ascending: parse_choice("sortDirection", sort_direction.as_deref(), false, &[
+			("desc", false),
+			("asc", true),
+		])? && !time_buckets,
+		use_cache: cache.unwrap_or(false),
+		stream_only: stream_only.unwrap_or(false),
+		normalize_separators: normalize_separators.unwrap_or(false),
+		literal_separator: literal_separator(glob_syntax.as_deref())?,
+		empty_dirs_only: empty_dirs_only.unwrap_or(false),
+		priority_paths: priority_paths.unwrap_or_default(),
+		populate_cache: populate_cache.unwrap_or(false),
+		force_include: force_include_patterns(force_include)?,
+		reinclude: reinclude.unwrap_or_default(),
+		dirs_with_matches: dirs_with_matches.unwrap_or(false),
+		max_path_length: max_path_length.map(|value| value as usize),
+		exact_depth: exact_depth.map(|value| value as usize),
+		allow_file_root: allow_file_root.unwrap_or(false),
+		newer_than_ms: newer_than_snapshot_ms,
+		return_snapshot: return_snapshot.unwrap_or(false),
+		group_by_top_dir: group_by_top_dir.unwrap_or(false),
+		max_results_per_group: max_results_per_group.map_or(usize::MAX, |value| value as usize),
+		max_results_per_dir: max_results_per_dir.map(|value| value as usize),
+		unique_by_basename: unique_by_basename.unwrap_or(false),
+		collapse_threshold: collapse_threshold.map(|value| value as usize),
+		max_result_bytes: max_result_bytes.map(|value| value as usize),
+		language_filter: match language_extensions {
+			Some(exts) => Some(grep::TypeFilter::Extensions(
+				exts
+					.into_iter()
+					.map(|ext| ext.trim_start_matches('.').to_string())
+					.collect(),
+			)),
+			None => grep::resolve_type_filter(language.as_deref()),
+		},
+		fuzzy: fuzzy.as_deref().and_then(FuzzyQuery::new),
+		as_tree: as_tree.unwrap_or(false),
+		partial_on_timeout: parse_choice("timeoutBehavior", timeout_behavior.as_deref(), false, &[
+			("error", false),
+			("partial", true),
+		])?,
+		name_regex,
+		cache_namespace: cache_namespace.unwrap_or_default(),
+		only_modified_in_git: only_modified_in_git.unwrap_or(false),
+		result_hash: result_hash.unwrap_or(false),
+		include_child_count: include_child_count.unwrap_or(false),
+		callback_interval: callback_min_interval_ms
+			.map(|value| Duration::from_millis(u64::from(value))),
+		include_order_index: include_order_index.unwrap_or(false),
+		only_git_tracked: only_git_tracked.unwrap_or(false),
+		auto_recursive: auto_recursive.unwrap_or(true),
+		format: parse_choice("format", format.as_deref(), ResultFormat::Objects, &[
+			("objects", ResultFormat::Objects),
+			("paths", ResultFormat::Paths),
+			("buffer", ResultFormat::Buffer),
+		])?,
+		match_resolved_path: match_resolved_path.unwrap_or(false),
+		broken_symlinks_only: broken_symlinks_only.unwrap_or(false),
+		dirs_first,
+		extension_breakdown: extension_breakdown.unwrap_or(false),
+		root_already_resolved: root_already_resolved.unwrap_or(false),
+		scan_progress: on_scan_progress,
+		native_separators: native_separators.unwrap_or(false) && std::path::MAIN_SEPARATOR != '/',
+		time_buckets,
+		canonicalize: canonicalize.unwrap_or(false),
+		require_match: require_match.unwrap_or(false),
+		content_type: parse_choice("contentType", content_type.as_deref(), None, &[
+			("image", Some(ContentType::Image)),
+			("archive", Some(ContentType::Archive)),
+			("pdf", Some(ContentType::Pdf)),
+			("text", Some(ContentType::Text)),
+			("binary", Some(ContentType::Binary)),
+		])?,
+		topmost_only: topmost_only.unwrap_or(false),
+		filter,
+		include_depth: include_depth.unwrap_or(false),
+		root_alias,
+		include_pattern_index: include_pattern_index.unwrap_or(false),
+		pattern,
+	};
+	Ok((config, ct))
+}
 
+/// Find filesystem entries matching a glob pattern.
+///
+/// Resolves the search root, scans entries, applies glob and optional file-type
+/// filters, and optionally streams each accepted match through `on_match`.
+///
+/// If `sortByMtime` is enabled, all matching entries are collected, sorted by
+/// mtime (descending unless `sortDirection` is `"asc"`), then truncated to
+/// `maxResults`. With `fuzzy`, entries are scored against the query instead
+/// of matched against `pattern`, and ranked by score.
+///
+/// # Errors
+/// Returns an error when the search path cannot be resolved, the path is not a
+/// directory, the glob pattern is invalid, `onlyModifiedInGit` or
+/// `onlyGitTracked` is set outside a git repository, or cancellation/timeout
+/// is triggered.
+#[napi(js_name = "glob")]
+pub fn glob(
+	options: GlobOptions<'_>,
+	#[napi(ts_arg_type = "((match: GlobMatch) => void) | undefined | null")] on_match: Option<
+		ThreadsafeFunction<GlobMatch>,
+	>,
+) -> task::Async<GlobResult> {
+	let (config, ct) = match glob_config(options) {
+		Ok(parsed) => parsed,
+		Err(err) => return task::blocking("glob", (), move |_| Err(err)),
+	};
 	task::blocking("glob", ct, move |ct| {
-		run_glob(
-			GlobConfig {
-				root: fs_cache::resolve_search_path(&path)?,
-				include_hidden: hidden.unwrap_or(false),
-				file_type_filter: file_type,
-				max_results: max_results.map_or(usize::MAX, |value| value as usize),
-				use_gitignore: gitignore.unwrap_or(true),
-				mentions_node_modules: include_node_modules
-					.unwrap_or_else(|| pattern.contains("node_modules")),
-				sort_by_mtime: sort_by_mtime.unwrap_or(false),
-				use_cache: cache.unwrap_or(false),
+		let glob_set = compile_glob(
+			&config.pattern,
+			config.normalize_separators,
+			config.literal_separator,
+			config.auto_recursive,
+		)?;
+		run_glob(config, &glob_set, on_match.as_ref(), ct)
+	})
+}
+
+/// Options for precompiling a glob pattern.
+#[napi(object)]
+pub struct CompilePatternOptions {
+	/// Glob pattern to compile (e.g., "*.ts").
+	pub pattern:              String,
+	/// Treat backslashes in the pattern as path separators on every platform
+	/// (default: false; always on for Windows).
+	#[napi(js_name = "normalizeSeparators")]
+	pub normalize_separators: Option<bool>,
+	/// Wildcard semantics, as for `glob` (default: `"default"`).
+	#[napi(js_name = "globSyntax")]
+	pub glob_syntax:          Option<String>,
+	/// Match slash-free patterns at any depth, as for `glob` (default: true).
+	#[napi(js_name = "autoRecursive")]
+	pub auto_recursive:       Option<bool>,
+}
+
+/// Options applied to every pattern `precompileGlobs` compiles; each field is
+/// as for `compilePattern`.
+#[napi(object)]
+#[derive(Default)]
+pub struct PrecompileGlobsOptions {
+	/// Treat backslashes in the patterns as path separators on every platform
+	/// (default: false; always on for Windows).
+	#[napi(js_name = "normalizeSeparators")]
+	pub normalize_separators: Option<bool>,
+	/// Wildcard semantics, as for `glob` (default: `"default"`).
+	#[napi(js_name = "globSyntax")]
+	pub glob_syntax:          Option<String>,
+	/// Match slash-free patterns at any depth, as for `glob` (default: true).
+	#[napi(js_name = "autoRecursive")]
+	pub auto_recursive:       Option<bool>,
+}
+
+/// Precompiled glob pattern, reusable across `globWith` calls.
+#[napi]
+pub struct GlobHandle {
+	pattern:  String,
+	glob_set: Arc<GlobSet>,
+}
+
+#[napi]
+impl GlobHandle {
+	/// The normalized pattern this handle was compiled from.
+	#[napi(getter)]
+	pub fn pattern(&self) -> String {
+		self.pattern.clone()
+	}
+}
+
+/// Compile a glob pattern once for repeated `globWith` queries.
+///
+/// # Errors
+/// Returns an error when the glob pattern or `globSyntax` is invalid.
+#[napi(js_name = "compilePattern")]
+pub fn compile_pattern(options: CompilePatternOptions) -> Result<GlobHandle> {
+	let pattern = effective_pattern(&options.pattern);
+	let glob_set = compile_glob(
+		&pattern,
+		options.normalize_separators.unwrap_or(false),
+		literal_separator(options.glob_syntax.as_deref())?,
+		options.auto_recursive.unwrap_or(true),
+	)?;
+	Ok(GlobHandle { pattern, glob_set: Arc::new(glob_set) })
+}
+
+/// Compile a batch of glob patterns up front, e.g. at startup, so their
+/// parsing cost stays off the hot path.
+///
+/// Handles are returned in `patterns` order, each compiled with `options` as
+/// `compilePattern` would.
+///
+/// # Errors
+/// Returns an error naming the first pattern that fails to compile, or when
+/// `globSyntax` is invalid.
+#[napi(js_name = "precompileGlobs")]
+pub fn precompile_globs(
+	patterns: Vec<String>,
+	options: Option<PrecompileGlobsOptions>,
+) -> Result<Vec<GlobHandle>> {
+	let PrecompileGlobsOptions { normalize_separators, glob_syntax, auto_recursive } =
+		options.unwrap_or_default();
+	patterns
+		.into_iter()
+		.map(|pattern| {
+			compile_pattern(CompilePatternOptions {
 				pattern,
-			},
-			on_match.as_ref(),
-			ct,
-		)
+				normalize_separators,
+				glob_syntax: glob_syntax.clone(),
+				auto_recursive,
+			})
+		})
+		.collect()
+}
+
+/// Find filesystem entries matching a precompiled glob pattern.
+///
+/// Behaves like `glob`, except the pattern comes from `handle`;
+/// `options.pattern`, `options.normalizeSeparators`, `options.globSyntax`, and
+/// `options.autoRecursive` are ignored.
+#[napi(js_name = "globWith")]
+pub fn glob_with(
+	handle: &GlobHandle,
+	mut options: GlobOptions<'_>,
+	#[napi(ts_arg_type = "((match: GlobMatch) => void) | undefined | null")] on_match: Option<
+		ThreadsafeFunction<GlobMatch>,
+	>,
+) -> task::Async<GlobResult> {
+	options.pattern.clone_from(&handle.pattern);
+	let (config, ct) = match glob_config(options) {
+		Ok(parsed) => parsed,
+		Err(err) => return task::blocking("glob", (), move |_| Err(err)),
+	};
+	let glob_set = Arc::clone(&handle.glob_set);
+	task::blocking("glob", ct, move |ct| run_glob(config, &glob_set, on_match.as_ref(), ct))
+}
+
+/// Options for `listAllPaths`.
+#[derive(Default)]
+#[napi(object)]
+pub struct ListPathsOptions<'env> {
+	/// Include hidden files (default: false).
+	pub hidden:               Option<bool>,
+	/// Respect .gitignore files (default: true).
+	pub gitignore:            Option<bool>,
+	/// Include `node_modules` entries (default: false).
+	#[napi(js_name = "includeNodeModules")]
+	pub include_node_modules: Option<bool>,
+	/// Abort signal for cancelling the operation.
+	pub signal:               Option<Unknown<'env>>,
+	/// Timeout in milliseconds for the operation.
+	#[napi(js_name = "timeoutMs")]
+	pub timeout_ms:           Option<u32>,
+}
+
+/// List the relative path of every scanned entry under `path`, in walk order.
+///
+/// Served from the shared scan cache (scanning on a miss) with no pattern
+/// matching at all, for callers that rank the whole index themselves, such as
+/// a JS fuzzy matcher. `.git` is always excluded.
+///
+/// # Errors
+/// Returns an error when `path` cannot be resolved or is not a directory, or
+/// cancellation/timeout is triggered.
+#[napi(js_name = "listAllPaths")]
+pub fn list_all_paths(
+	path: String,
+	options: Option<ListPathsOptions<'_>>,
+) -> task::Async<Vec<String>> {
+	let ListPathsOptions { hidden, gitignore, include_node_modules, signal, timeout_ms } =
+		options.unwrap_or_default();
+	let scan_options =
+		fs_cache::ScanOptions::new(hidden.unwrap_or(false), gitignore.unwrap_or(true));
+	let include_node_modules = include_node_modules.unwrap_or(false);
+	let ct = task::CancelToken::new(timeout_ms, signal);
+	task::blocking("list_all_paths", ct, move |ct| {
+		let root = fs_cache::resolve_search_path(&path)?;
+		let scan =
+			fs_cache::get_or_scan(&root, scan_options, &[], fs_cache::SHARED_NAMESPACE, None, &ct)?;
+		Ok(scan
+			.entries
+			.into_iter()
+			.filter(|entry| !fs_cache::should_skip_path(Path::new(&entry.path), include_node_modules))
+			.map(|entry| entry.path)
+			.collect())
 	})
 }
+
+/// Match a glob pattern against a caller-supplied entry list without touching
+/// the filesystem.
+///
+/// Applies the same filtering, ordering, and grouping as `glob`. `entries`
+/// should be in walker order (each directory before its descendants) for
+/// `emptyDirsOnly`. Options that drive scanning or the cache (`path`,
+/// `hidden`, `gitignore`, `cache`, `forceInclude`, `onlyModifiedInGit`,
+/// `onlyGitTracked`, ...) are ignored.
+///
+/// # Errors
+/// Returns an error when the glob pattern is invalid or the timeout/signal
+/// fires.
+#[napi(js_name = "globMatchEntries")]
+pub fn glob_match_entries(entries: Vec<GlobMatch>, options: GlobOptions<'_>) -> Result<GlobResult> {
+	let (config, ct) = glob_config(options)?;
+	let glob_set = compile_glob(
+		&config.pattern,
+		config.normalize_separators,
+		config.literal_separator,
+		config.auto_recursive,
+	)?;
+	let mut outcome = filter_entries(&entries, &glob_set, &config, None, None, None, &ct)?;
+	order_matches(&config, &mut outcome);
+	let FilterOutcome { matches, matched, skipped_long_paths, truncated, timed_out, extensions } =
+		outcome;
+	config.require_match(matched, timed_out)?;
+	let result_hash = config.result_hash(&matches);
+	let (matches, paths) = config.format_matches(matches);
+	let (matches, tree) = config.tree_matches(matches);
+	let (matches, groups) = config.group_matches(matches);
+	let (matches, time_buckets) = config.bucket_matches(matches, now_ms());
+	let (matches, buffer) = config.encode_matches(matches);
+	Ok(config.output_result(GlobResult {
+		matches,
+		total_matches: matched.min(config.max_results).min(u32::MAX as usize) as u32,
+		rescan_skipped_due_to_budget: false,
+		scan_capped: false,
+		symlink_loops_detected: 0,
+		gitignore_applied: false,
+		from_cache: false,
+		skipped_long_paths: skipped_long_paths.min(u32::MAX as usize) as u32,
+		truncated,
+		snapshot_ms: None,
+		groups,
+		tree,
+		timed_out,
+		result_hash,
+		paths,
+		extension_breakdown: extensions,
+		time_buckets,
+		buffer,
+	}))
+}
+
+/// Outcome of [`native_self_test`].
+#[napi(object)]
+pub struct SelfTestReport {
+	/// Whether the walk and glob produced the expected entries.
+	pub ok:             bool,
+	/// Number of entries the walker discovered in the probe directory.
+	pub walked_entries: u32,
+	/// Failure description when `ok` is false.
+	pub error:          Option<String>,
+}
+
+/// Walks a freshly created probe directory and globs it for `*.rs`.
+fn run_self_test(dir: &Path) -> std::result::Result<u32, String> {
+	std::fs::create_dir_all(dir.join("sub")).map_err(|err| format!("create dir: {err}"))?;
+	for file in ["a.rs", "b.txt", "sub/c.rs"] {
+		std::fs::write(dir.join(file), "").map_err(|err| format!("write {file}: {err}"))?;
+	}
+
+	let root = std::fs::canonicalize(dir).map_err(|err| format!("canonicalize: {err}"))?;
+	let entries = fs_cache::force_rescan(
+		&root,
+		fs_cache::ScanOptions::new(true, false),
+		&[],
+		fs_cache::SHARED_NAMESPACE,
+		false,
+		None,
+		&task::CancelToken::default(),
+	)
+	.map_err(|err| format!("walk: {err}"))?
+	.entries;
+	let walked = entries.len().min(u32::MAX as usize) as u32;
+	if entries.len() != 4 {
+		return Err(format!("expected 4 walked entries, found {walked}"));
+	}
+
+	let glob_set =
+		compile_glob("*.rs", false, false, true).map_err(|err| format!("compile: {err}"))?;
+	let matched = entries
+		.iter()
+		.filter(|entry| glob_set.is_match(&entry.path))
+		.count();
+	if matched != 2 {
+		return Err(format!("expected 2 glob matches, found {matched}"));
+	}
+	Ok(walked)
+}
+
+/// Smoke-test the native module: walk a temp directory and glob it.
+///
+/// Exercises the walker, entry collection, and glob compilation end to end so
+/// environmental problems can be told apart from query problems.
+#[napi(js_name = "nativeSelfTest")]
+pub fn native_self_test() -> SelfTestReport {
+	let nanos = std::time::SystemTime::now()
+		.duration_since(std::time::UNIX_EPOCH)
+		.map_or(0, |d| d.as_nanos());
+	let dir =
+		std::env::temp_dir().join(format!("pi-natives-selftest-{}-{nanos}", std::process::id()));
+	let result = run_self_test(&dir);
+	let _ = std::fs::remove_dir_all(&dir);
+	match result {
+		Ok(walked_entries) => SelfTestReport { ok: true, walked_entries, error: None },
+		Err(error) => {
+			SelfTestReport { ok: false, walked_entries: 0, error: Some(error) }
+		},
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fs_cache::tests::TempDir;
+
+	/// A scanned entry with no mtime or annotations.
+	fn entry(path: &str, file_type: FileType) -> GlobMatch {
+		GlobMatch::new(path.to_string(), file_type, None)
+	}
+
+	fn file(path: &str) -> GlobMatch {
+		entry(path, FileType::File)
+	}
+
+	#[test]
+	fn test_build_glob_pattern_prefix() {
+		assert_eq!(build_glob_pattern("*.rs", false, true), "**/*.rs");
+		assert_eq!(build_glob_pattern("*.rs", false, false), "*.rs");
+		assert_eq!(build_glob_pattern("src/*.rs", false, true), "src/*.rs");
+		assert_eq!(build_glob_pattern("**/*.rs", false, true), "**/*.rs");
+	}
+
+	#[test]
+	fn test_trailing_slash_matches_directories_only() {
+		assert_eq!(build_glob_pattern("build/", false, true), "**/build");
+		assert_eq!(build_glob_pattern("src/gen/", false, true), "src/gen");
+		assert!(is_dir_pattern("build/", false));
+		assert!(!is_dir_pattern("build", false));
+		assert!(!is_dir_pattern("/", false));
+
+		let entries = vec![
+			entry("build", FileType::Dir),
+			entry("build/out.js", FileType::File),
+			entry("docs", FileType::Dir),
+			entry("docs/build", FileType::File),
+		];
+		let options = GlobOptions { pattern: "build/".to_string(), ..Default::default() };
+		let result = glob_match_entries(entries, options).unwrap();
+		let paths: Vec<_> = result.matches.iter().map(|m| m.path.as_str()).collect();
+		assert_eq!(paths, ["build"]);
+	}
+
+	#[test]
+	fn test_build_glob_pattern_normalize_separators() {
+		assert_eq!(build_glob_pattern("src\\**\\*.rs", true, true), "src/**/*.rs");
+		let set = compile_glob("src\\**\\*.rs", true, false, true).unwrap();
+		assert!(set.is_match("src/a/b.rs"));
+	}
+
+	#[cfg(not(windows))]
+	#[test]
+	fn test_build_glob_pattern_keeps_backslash_escapes() {
+		assert_eq!(build_glob_pattern("a\\*b", false, true), "**/a\\*b");
+		let set = compile_glob("a\\*b", false, false, true).unwrap();
+		assert!(set.is_match("dir/a*b"));
+		assert!(!set.is_match("dir/axb"));
+	}
+
+	#[test]
+	fn test_compile_glob_literal_separator() {
+		let set = compile_glob("src/*.rs", false, false, true).unwrap();
+		assert!(set.is_match("src/sub/b.rs"));
+		let set =
+			compile_glob("src/*.rs", false, literal_separator(Some("bash")).unwrap(), true).unwrap();
+		assert!(set.is_match("src/a.rs"));
+		assert!(!set.is_match("src/sub/b.rs"));
+		let set = compile_glob("*.rs", false, true, true).unwrap();
+		assert!(set.is_match("src/sub/b.rs"));
+	}
+
+	#[test]
+	fn test_compile_glob_shallow_without_auto_recursive() {
+		let set = compile_glob("*.rs", false, false, false).unwrap();
+		assert!(set.is_match("a.rs"));
+		assert!(!set.is_match("src/a.rs"));
+		let set = compile_glob("src/**/*.rs", false, false, false).unwrap();
+		assert!(set.is_match("src/sub/b.rs"));
+	}
+
+	#[test]
+	fn test_is_empty_dir() {
+		let entries = [
+			entry("a", FileType::Dir),
+			entry("a/b", FileType::Dir),
+			entry("a/b/c.rs", FileType::File),
+			entry("a/d", FileType::Dir),
+			entry("a-e", FileType::Dir),
+			entry("f.rs", FileType::File),
+		];
+		let empty: Vec<_> = (0..entries.len())
+			.filter(|&index| is_empty_dir(&entries, index))
+			.collect();
+		assert_eq!(empty, [3, 4]);
+	}
+
+	#[test]
+	fn test_collapse_matches() {
+		let matches = vec![
+			file("a.rs"),
+			file("gen/x/1.rs"),
+			file("gen/x/2.rs"),
+			file("gen/x/3.rs"),
+			file("gen/y.rs"),
+			file("gen/z.rs"),
+			file("src/lib.rs"),
+		];
+		let collapsed: Vec<_> = collapse_matches(matches, 2)
+			.into_iter()
+			.map(|entry| (entry.path, entry.collapsed_count))
+			.collect();
+		// `gen/x` collapses first; `gen` then still lists three entries.
+		assert_eq!(collapsed, [
+			("a.rs".to_string(), None),
+			("gen".to_string(), Some(5)),
+			("src/lib.rs".to_string(), None),
+		]);
+	}
+
+	#[test]
+	fn test_glob_match_entries_file_types() {
+		let entries = vec![
+			entry("dir", FileType::Dir),
+			entry("file", FileType::File),
+			entry("link", FileType::Symlink),
+		];
+		let paths = |file_type, file_types| {
+			let options =
+				GlobOptions { pattern: "*".to_string(), file_type, file_types, ..Default::default() };
+			let result = glob_match_entries(entries.clone(), options).unwrap();
+			result
+				.matches
+				.into_iter()
+				.map(|entry| entry.path)
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(paths(None, Some(vec![FileType::File, FileType::Symlink])), ["file", "link"]);
+		assert_eq!(paths(Some(FileType::Dir), Some(vec![FileType::File])), ["dir", "file"]);
+		assert_eq!(paths(None, Some(Vec::new())), ["dir", "file", "link"]);
+	}
+
+	#[test]
+	fn test_glob_match_entries() {
+		let entries = vec![
+			entry("pkg", FileType::Dir),
+			entry("pkg/a", FileType::Dir),
+			entry("pkg/a/lib.rs", FileType::File),
+			entry("pkg/b", FileType::Dir),
+			entry("node_modules/x.rs", FileType::File),
+		];
+		let options = GlobOptions {
+			pattern: "*".to_string(),
+			file_type: Some(FileType::Dir),
+			exact_depth: Some(1),
+			..Default::default()
+		};
+		let result = glob_match_entries(entries.clone(), options).unwrap();
+		let paths: Vec<_> = result.matches.iter().map(|m| m.path.as_str()).collect();
+		assert_eq!(paths, ["pkg/a", "pkg/b"]);
+
+		let options = GlobOptions { pattern: "*.rs".to_string(), ..Default::default() };
+		let result = glob_match_entries(entries, options).unwrap();
+		let paths: Vec<_> = result.matches.iter().map(|m| m.path.as_str()).collect();
+		assert_eq!(paths, ["pkg/a/lib.rs"]);
+	}
+
+	#[test]
+	fn test_glob_match_entries_fuzzy() {
+		let entries =
+			vec![file("src/foo/zfile.rs"), file("fzf.rs"), file("README.md"), file("src/fzf_util.rs")];
+		let options = GlobOptions { fuzzy: Some("fzf".to_string()), ..Default::default() };
+		let result = glob_match_entries(entries, options).unwrap();
+		let paths: Vec<_> = result.matches.iter().map(|m| m.path.as_str()).collect();
+		assert_eq!(paths, ["fzf.rs", "src/fzf_util.rs", "src/foo/zfile.rs"]);
+		assert!(
+			result
+				.matches
+				.windows(2)
+				.all(|pair| pair[0].score >= pair[1].score)
+		);
+	}
+
+	#[test]
+	fn test_glob_match_entries_as_tree() {
+		let entries = vec![
+			entry("README.md", FileType::File),
+			entry("src", FileType::Dir),
+			entry("src/lib.rs", FileType::File),
+			entry("src/sub/mod.rs", FileType::File),
+		];
+		let options = GlobOptions {
+			pattern: "**/*.{rs,md}".to_string(),
+			as_tree: Some(true),
+			..Default::default()
+		};
+		let result = glob_match_entries(entries, options).unwrap();
+		assert!(result.matches.is_empty());
+		let root = result.tree.unwrap();
+		let names = |node: &GlobTreeNode| {
+			node
+				.children
+				.iter()
+				.map(|c| c.name.clone())
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(names(&root), ["README.md", "src"]);
+		let src = &root.children[1];
+		assert!(!src.matched);
+		assert_eq!(names(src), ["lib.rs", "sub"]);
+		let sub = &src.children[1];
+		assert_eq!(
+			(sub.path.as_str(), sub.file_type, sub.matched),
+			("src/sub", FileType::Dir, false)
+		);
+		assert_eq!(sub.children[0].path, "src/sub/mod.rs");
+		assert!(sub.children[0].matched);
+	}
+
+	#[test]
+	fn test_glob_match_entries_name_regex() {
+		let entries =
+			vec![file("src/test_a.rs"), file("src/a.rs"), file("test_b.rs"), file("src/test_c.ts")];
+		let options = GlobOptions {
+			pattern: "src/**".to_string(),
+			name_regex: Some(r"^test_.*\.rs$".to_string()),
+			..Default::default()
+		};
+		let result = glob_match_entries(entries.clone(), options).unwrap();
+		let paths: Vec<_> = result.matches.iter().map(|m| m.path.as_str()).collect();
+		assert_eq!(paths, ["src/test_a.rs"]);
+
+		let options = GlobOptions { name_regex: Some("(".to_string()), ..Default::default() };
+		let err = glob_match_entries(entries, options).err().unwrap();
+		assert_eq!(error::code_of(&err), Some(ErrorCode::InvalidPattern));
+	}
+
+	#[test]
+	fn test_glob_match_entries_max_results_per_dir() {
+		let entries = vec![
+			file("a.rs"),
+			file("b.rs"),
+			file("src/c.rs"),
+			file("src/d.rs"),
+			file("src/e.rs"),
+			file("src/sub/f.rs"),
+		];
+		let options = GlobOptions {
+			pattern: "*.rs".to_string(),
+			max_results_per_dir: Some(2),
+			..Default::default()
+		};
+		let result = glob_match_entries(entries, options).unwrap();
+		let paths: Vec<_> = result.matches.iter().map(|m| m.path.as_str()).collect();
+		assert_eq!(paths, ["a.rs", "b.rs", "src/c.rs", "src/d.rs", "src/sub/f.rs"]);
+	}
+
+	#[test]
+	fn test_glob_match_entries_mtime_top_k() {
+		let entries: Vec<_> = (0..50)
+			.map(|index| {
+				let mtime = f64::from(index * 7 % 10);
+				GlobMatch::new(format!("f{index:02}.rs"), FileType::File, Some(mtime))
+			})
+			.collect();
+		let paths = |max_results, sort_direction: &str| {
+			let options = GlobOptions {
+				pattern: "*.rs".to_string(),
+				sort_by_mtime: Some(true),
+				sort_direction: Some(sort_direction.to_string()),
+				max_results,
+				..Default::default()
+			};
+			let result = glob_match_entries(entries.clone(), options).unwrap();
+			result
+				.matches
+				.into_iter()
+				.map(|entry| entry.path)
+				.collect::<Vec<_>>()
+		};
+		for direction in ["asc", "desc"] {
+			let all = paths(None, direction);
+			assert_eq!(all.len(), 50);
+			assert_eq!(paths(Some(7), direction), all[..7]);
+		}
+		assert_eq!(paths(Some(2), "desc"), ["f47.rs", "f37.rs"]);
+	}
+
+	#[test]
+	fn test_glob_config_rejects_unknown_choices() {
+		let rejects = |options: GlobOptions<'static>| {
+			let err = glob_config(options).err().unwrap();
+			error::code_of(&err) == Some(ErrorCode::InvalidPattern)
+		};
+		assert!(rejects(GlobOptions { sort_direction: Some("up".into()), ..Default::default() }));
+		assert!(rejects(GlobOptions {
+			timeout_behavior: Some("ignore".into()),
+			..Default::default()
+		}));
+		assert!(rejects(GlobOptions { glob_syntax: Some("zsh".into()), ..Default::default() }));
+		assert!(rejects(GlobOptions { format: Some("path".into()), ..Default::default() }));
+		assert!(rejects(GlobOptions { content_type: Some("video".into()), ..Default::default() }));
+		assert!(rejects(GlobOptions {
+			force_include: Some(vec!["**/dist/*.js".into()]),
+			..Default::default()
+		}));
+		assert!(rejects(GlobOptions {
+			force_include: Some(vec!["./*/out".into()]),
+			..Default::default()
+		}));
+		assert!(rejects(GlobOptions {
+			force_include: Some(vec!["*.lock".into()]),
+			..Default::default()
+		}));
+		let (config, _) =
+			glob_config(GlobOptions { sort_direction: Some("asc".into()), ..Default::default() })
+				.unwrap();
+		assert!(config.sort_ascending);
+		let (config, _) = glob_config(GlobOptions {
+			force_include: Some(vec!["dist/**/*.js".into(), "./gen/a.rs".into()]),
+			..Default::default()
+		})
+		.unwrap();
+		assert_eq!(config.force_include.len(), 2);
+	}
+
+	#[test]
+	fn test_time_buckets_by_age() {
+		let now = 100.0 * DAY_MS;
+		let entry = |path: &str, age_days: Option<f64>| {
+			GlobMatch::new(
+				path.to_string(),
+				FileType::File,
+				age_days.map(|days| days.mul_add(-DAY_MS, now)),
+			)
+		};
+		let (config, _) =
+			glob_config(GlobOptions { time_buckets: Some(true), ..Default::default() }).unwrap();
+		assert!(config.sort_by_mtime && !config.sort_ascending);
+		let matches = vec![
+			entry("a", Some(0.5)),
+			entry("b", Some(1.5)),
+			entry("c", Some(0.9)),
+			entry("d", Some(30.0)),
+			entry("e", None),
+		];
+		let (matches, buckets) = config.bucket_matches(matches, now);
+		assert!(matches.is_empty());
+		let buckets: Vec<_> = buckets
+			.unwrap()
+			.into_iter()
+			.map(|bucket| {
+				let paths: Vec<_> = bucket.matches.into_iter().map(|entry| entry.path).collect();
+				(bucket.bucket, paths)
+			})
+			.collect();
+		assert_eq!(buckets, [
+			("today".to_string(), vec!["a".to_string(), "c".to_string()]),
+			("yesterday".to_string(), vec!["b".to_string()]),
+			("older".to_string(), vec!["d".to_string(), "e".to_string()]),
+		]);
+	}
+
+	#[test]
+	fn test_topmost_only_drops_descendants_of_matched_dirs() {
+		let entries: Vec<_> = [
+			("build", FileType::Dir),
+			("build/out", FileType::Dir),
+			("build/out/app.js", FileType::File),
+			("build/out/build.log", FileType::File),
+			("builder.js", FileType::File),
+			("src", FileType::Dir),
+			("src/build", FileType::Dir),
+			("src/build/x.js", FileType::File),
+		]
+		.into_iter()
+		.map(|(path, file_type)| entry(path, file_type))
+		.collect();
+		let options = GlobOptions {
+			pattern: "**/build*".to_string(),
+			topmost_only: Some(true),
+			..Default::default()
+		};
+		let result = glob_match_entries(entries, options).unwrap();
+		let paths: Vec<_> = result
+			.matches
+			.iter()
+			.map(|entry| entry.path.as_str())
+			.collect();
+		assert_eq!(paths, ["build", "builder.js", "src/build"]);
+		assert_eq!(result.total_matches, 3);
+	}
+
+	#[test]
+	fn test_require_match_errors_on_empty_result() {
+		let entries = vec![file("a.rs")];
+		let run = |pattern: &str| {
+			glob_match_entries(entries.clone(), GlobOptions {
+				pattern: pattern.to_string(),
+				require_match: Some(true),
+				..Default::default()
+			})
+		};
+		assert_eq!(run("*.rs").unwrap().total_matches, 1);
+		let err = run("*.ts").err().unwrap();
+		assert_eq!(error::code_of(&err), Some(ErrorCode::NoMatch));
+	}
+
+	#[test]
+	fn test_native_separators_only_rewrite_output() {
+		let entries = vec![file("src/a.rs"), file("src/b/c.rs")];
+		let options = GlobOptions {
+			pattern: "src/**/*.rs".to_string(),
+			group_by_top_dir: Some(true),
+			native_separators: Some(true),
+			..Default::default()
+		};
+		let result = glob_match_entries(entries, options).unwrap();
+		let groups = result.groups.unwrap();
+		assert_eq!(groups[0].dir, "src");
+		let paths: Vec<_> = groups[0]
+			.matches
+			.iter()
+			.map(|entry| entry.path.as_str())
+			.collect();
+		let native = |path: &str| path.replace('/', std::path::MAIN_SEPARATOR_STR);
+		assert_eq!(paths, [native("src/a.rs"), native("src/b/c.rs")]);
+	}
+
+	#[test]
+	fn test_root_alias_prefixes_output_paths() {
+		let entries = vec![file("a.rs"), file("src/b.rs")];
+		let options = |format: Option<&str>| GlobOptions {
+			pattern: "**/*.rs".to_string(),
+			format: format.map(str::to_string),
+			root_alias: Some("@workspace".to_string()),
+			..Default::default()
+		};
+		let result = glob_match_entries(entries.clone(), options(None)).unwrap();
+		let paths: Vec<_> = result
+			.matches
+			.iter()
+			.map(|entry| entry.path.as_str())
+			.collect();
+		assert_eq!(paths, ["@workspace/a.rs", "@workspace/src/b.rs"]);
+		let result = glob_match_entries(entries, options(Some("paths"))).unwrap();
+		assert_eq!(result.paths.as_deref(), Some("@workspace/a.rs\n@workspace/src/b.rs"));
+	}
+
+	#[test]
+	fn test_glob_match_entries_dirs_first() {
+		let entries = vec![
+			entry("a.rs", FileType::File),
+			entry("b", FileType::Dir),
+			entry("c.rs", FileType::Symlink),
+			entry("d", FileType::Dir),
+		];
+		let paths = |dirs_first, max_results| {
+			let options =
+				GlobOptions { pattern: "*".to_string(), dirs_first, max_results, ..Default::default() };
+			let result = glob_match_entries(entries.clone(), options).unwrap();
+			result
+				.matches
+				.into_iter()
+				.map(|entry| entry.path)
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(paths(None, None), ["a.rs", "b", "c.rs", "d"]);
+		assert_eq!(paths(Some(true), None), ["b", "d", "a.rs", "c.rs"]);
+		assert_eq!(paths(Some(false), None), ["a.rs", "c.rs", "b", "d"]);
+		assert_eq!(paths(Some(true), Some(1)), ["b"]);
+	}
+
+	#[test]
+	fn test_glob_match_entries_extension_breakdown() {
+		let entries = vec![
+			entry("src", FileType::Dir),
+			entry("src/a.ts", FileType::File),
+			entry("src/b.ts", FileType::File),
+			entry("src/c.test.ts", FileType::File),
+			entry("src/d.rs", FileType::File),
+			entry("Makefile", FileType::File),
+		];
+		let options = GlobOptions {
+			pattern: "src/[ab].*".to_string(),
+			max_results: Some(1),
+			extension_breakdown: Some(true),
+			..Default::default()
+		};
+		let result = glob_match_entries(entries, options).unwrap();
+		assert_eq!(result.matches.len(), 1);
+		let breakdown = result.extension_breakdown.unwrap();
+		let counts = |extension: &str| {
+			let counts = breakdown[extension];
+			(counts.scanned, counts.matched)
+		};
+		assert_eq!(breakdown.len(), 3);
+		assert_eq!(counts("ts"), (3, 2));
+		assert_eq!(counts("rs"), (1, 0));
+		assert_eq!(counts(""), (1, 0));
+	}
+
+	#[test]
+	fn test_glob_match_entries_unique_by_basename() {
+		let entry =
+			|path: &str, mtime: f64| GlobMatch::new(path.to_string(), FileType::File, Some(mtime));
+		let entries = vec![
+			entry("a/index.ts", 1.0),
+			entry("a/util.ts", 1.0),
+			entry("b/index.ts", 3.0),
+			entry("c/index.ts", 2.0),
+		];
+		let paths = |sort_by_mtime| {
+			let options = GlobOptions {
+				pattern: "**/*.ts".to_string(),
+				unique_by_basename: Some(true),
+				sort_by_mtime: Some(sort_by_mtime),
+				..Default::default()
+			};
+			let result = glob_match_entries(entries.clone(), options).unwrap();
+			assert_eq!(result.total_matches, 2);
+			result
+				.matches
+				.into_iter()
+				.map(|m| m.path)
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(paths(false), ["a/index.ts", "a/util.ts"]);
+		assert_eq!(paths(true), ["b/index.ts", "a/util.ts"]);
+	}
+
+	#[test]
+	fn test_git_paths() {
+		let dir = TempDir::new("git-changed");
+		std::fs::create_dir_all(dir.join("sub/new")).unwrap();
+		let git = |args: &[&str]| {
+			let status = std::process::Command::new("git")
+				.arg("-C")
+				.arg(&*dir)
+				.args(args)
+				.output()
+				.unwrap()
+				.status;
+			assert!(status.success());
+		};
+		git(&["init", "-q"]);
+		std::fs::write(dir.join("top.rs"), "").unwrap();
+		std::fs::write(dir.join("sub/staged.rs"), "").unwrap();
+		git(&["add", "sub/staged.rs"]);
+		std::fs::write(dir.join("sub/new/untracked.rs"), "").unwrap();
+
+		let changed = git_changed_paths(&dir.join("sub")).unwrap();
+		let mut changed: Vec<_> = changed.into_iter().collect();
+		changed.sort();
+		assert_eq!(changed, ["new/untracked.rs", "staged.rs"]);
+		let tracked = git_tracked_paths(&dir.join("sub")).unwrap();
+		assert_eq!(tracked, HashSet::from(["staged.rs".to_string()]));
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn test_match_resolved_path() {
+		let dir = TempDir::new("resolved");
+		std::fs::create_dir_all(dir.join("vendor")).unwrap();
+		std::fs::create_dir_all(dir.join("src")).unwrap();
+		std::fs::write(dir.join("vendor/real.rs"), "").unwrap();
+		std::fs::write(dir.join("src/own.rs"), "").unwrap();
+		std::os::unix::fs::symlink("../vendor/real.rs", dir.join("src/link.rs")).unwrap();
+		let entries: Vec<_> = ["src/link.rs", "src/own.rs"]
+			.into_iter()
+			.map(file)
+			.collect();
+		let matched = |match_resolved_path| {
+			let (config, ct) = glob_config(GlobOptions {
+				pattern: "**/vendor/**".to_string(),
+				match_resolved_path: Some(match_resolved_path),
+				..Default::default()
+			})
+			.unwrap();
+			let glob_set = compile_glob(&config.pattern, false, false, true).unwrap();
+			filter_entries(&entries, &glob_set, &config, Some(&*dir), None, None, &ct)
+				.unwrap()
+				.matches
+				.into_iter()
+				.map(|entry| entry.path)
+				.collect::<Vec<_>>()
+		};
+		assert!(matched(false).is_empty());
+		assert_eq!(matched(true), ["src/link.rs"]);
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn test_canonicalize_sets_real_path() {
+		let tmp = TempDir::new("real-path");
+		std::fs::write(tmp.join("real.rs"), "").unwrap();
+		std::os::unix::fs::symlink("real.rs", tmp.join("link.rs")).unwrap();
+		std::os::unix::fs::symlink("missing.rs", tmp.join("broken.rs")).unwrap();
+		let dir = std::fs::canonicalize(&tmp).unwrap();
+		let entries: Vec<_> = ["broken.rs", "link.rs", "real.rs"]
+			.into_iter()
+			.map(file)
+			.collect();
+		let (config, ct) = glob_config(GlobOptions {
+			pattern: "*.rs".to_string(),
+			canonicalize: Some(true),
+			..Default::default()
+		})
+		.unwrap();
+		let glob_set = compile_glob(&config.pattern, false, false, true).unwrap();
+		let real_paths: Vec<_> =
+			filter_entries(&entries, &glob_set, &config, Some(&dir), None, None, &ct)
+				.unwrap()
+				.matches
+				.into_iter()
+				.map(|entry| entry.real_path)
+				.collect();
+		let real = dir.join("real.rs").to_string_lossy().into_owned();
+		assert_eq!(real_paths, [None, Some(real.clone()), Some(real)]);
+	}
+
+	#[test]
+	fn test_content_type_sniffs_headers() {
+		let dir = TempDir::new("sniff");
+		std::fs::create_dir_all(dir.join("sub")).unwrap();
+		std::fs::write(dir.join("logo.txt"), b"\x89PNG\r\n\x1a\n\0\0").unwrap();
+		std::fs::write(dir.join("notes.bin"), "plain text").unwrap();
+		std::fs::write(dir.join("blob.dat"), b"ab\0cd").unwrap();
+		let entries: Vec<_> = [
+			("blob.dat", FileType::File),
+			("logo.txt", FileType::File),
+			("notes.bin", FileType::File),
+			("sub", FileType::Dir),
+		]
+		.into_iter()
+		.map(|(path, file_type)| entry(path, file_type))
+		.collect();
+		let matched = |content_type: &str| {
+			let (config, ct) = glob_config(GlobOptions {
+				pattern: "*".to_string(),
+				content_type: Some(content_type.to_string()),
+				..Default::default()
+			})
+			.unwrap();
+			let glob_set = compile_glob(&config.pattern, false, false, true).unwrap();
+			filter_entries(&entries, &glob_set, &config, Some(&*dir), None, None, &ct)
+				.unwrap()
+				.matches
+				.into_iter()
+				.map(|entry| entry.path)
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(matched("image"), ["logo.txt"]);
+		assert_eq!(matched("text"), ["notes.bin"]);
+		assert_eq!(matched("binary"), ["blob.dat", "logo.txt"]);
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn test_broken_symlinks_only() {
+		let dir = TempDir::new("broken");
+		std::fs::write(dir.join("target.rs"), "").unwrap();
+		std::os::unix::fs::symlink("target.rs", dir.join("valid.rs")).unwrap();
+		std::os::unix::fs::symlink("missing.rs", dir.join("dangling.rs")).unwrap();
+		let entries = vec![
+			entry("dangling.rs", FileType::Symlink),
+			file("target.rs"),
+			entry("valid.rs", FileType::Symlink),
+		];
+		let (config, ct) = glob_config(GlobOptions {
+			pattern: "*.rs".to_string(),
+			broken_symlinks_only: Some(true),
+			..Default::default()
+		})
+		.unwrap();
+		let glob_set = compile_glob(&config.pattern, false, false, true).unwrap();
+		let outcome =
+			filter_entries(&entries, &glob_set, &config, Some(&*dir), None, None, &ct).unwrap();
+		let paths: Vec<_> = outcome.matches.iter().map(|m| m.path.as_str()).collect();
+		assert_eq!(paths, ["dangling.rs"]);
+	}
+
+	#[test]
+	fn test_precompile_globs_applies_options_to_every_pattern() {
+		let crosses_dirs = |options| {
+			precompile_globs(vec!["src/*.rs".to_string(), "docs/*.md".to_string()], options)
+				.unwrap()
+				.iter()
+				.zip(["src/a/b.rs", "docs/a/b.md"])
+				.map(|(handle, path)| handle.glob_set.is_match(path))
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(crosses_dirs(None), [true, true]);
+		let bash = PrecompileGlobsOptions { glob_syntax: Some("bash".into()), ..Default::default() };
+		assert_eq!(crosses_dirs(Some(bash)), [false, false]);
+		let zsh = PrecompileGlobsOptions { glob_syntax: Some("zsh".into()), ..Default::default() };
+		assert!(precompile_globs(vec!["*.rs".to_string()], Some(zsh)).is_err());
+	}
+
+	#[test]
+	fn test_stale_empty_cached_scan_is_rechecked() {
+		let dir = TempDir::new("glob-recheck");
+		let root = std::fs::canonicalize(&dir).unwrap();
+		let run = || {
+			let (config, ct) = glob_config(GlobOptions {
+				pattern: "*.rs".to_string(),
+				path: root.to_str().unwrap().to_string(),
+				cache: Some(true),
+				cache_namespace: Some("test-recheck".to_string()),
+				..Default::default()
+			})
+			.unwrap();
+			let glob_set = compile_glob(&config.pattern, false, false, true).unwrap();
+			run_glob(config, &glob_set, None, ct).unwrap()
+		};
+
+		// The slow walk leaves a cached scan already past the recheck threshold.
+		fs_cache::SCAN_TEST_DELAY.set(Duration::from_millis(fs_cache::empty_recheck_ms() + 50));
+		assert!(run().matches.is_empty());
+		fs_cache::SCAN_TEST_DELAY.set(Duration::ZERO);
+		std::fs::write(dir.join("a.rs"), "").unwrap();
+		let result = run();
+		assert!(!result.from_cache);
+		let paths: Vec<_> = result.matches.iter().map(|m| m.path.as_str()).collect();
+		assert_eq!(paths, ["a.rs"]);
+	}
+
+	#[test]
+	fn test_file_root_respects_max_results() {
+		let dir = TempDir::new("glob-file-root");
+		std::fs::write(dir.join("a.rs"), "").unwrap();
+		let run = |max_results| {
+			let (config, ct) = glob_config(GlobOptions {
+				pattern: "*.rs".to_string(),
+				path: dir.join("a.rs").to_str().unwrap().to_string(),
+				allow_file_root: Some(true),
+				max_results,
+				..Default::default()
+			})
+			.unwrap();
+			let glob_set = compile_glob(&config.pattern, false, false, true).unwrap();
+			let result = run_glob(config, &glob_set, None, ct).unwrap();
+			(result.matches.len(), result.total_matches)
+		};
+		assert_eq!(run(None), (1, 1));
+		assert_eq!(run(Some(0)), (0, 0));
+	}
+
+	#[test]
+	fn test_root_already_resolved_skips_validation() {
+		let missing = std::env::temp_dir().join(format!("pi-natives-missing-{}", std::process::id()));
+		let run = |root_already_resolved| {
+			let (config, ct) = glob_config(GlobOptions {
+				pattern: "*".to_string(),
+				path: missing.to_str().unwrap().to_string(),
+				root_already_resolved: Some(root_already_resolved),
+				..Default::default()
+			})
+			.unwrap();
+			let glob_set = compile_glob(&config.pattern, false, false, true).unwrap();
+			run_glob(config, &glob_set, None, ct)
+		};
+		let err = run(false).err().unwrap();
+		assert_eq!(error::code_of(&err), Some(ErrorCode::PathNotFound));
+		assert!(run(true).unwrap().matches.is_empty());
+	}
+
+	#[test]
+	fn test_glob_match_entries_result_hash() {
+		let entry =
+			|path: &str, mtime: f64| GlobMatch::new(path.to_string(), FileType::File, Some(mtime));
+		let hash = |entries: Vec<GlobMatch>| {
+			let options = GlobOptions {
+				pattern: "*.rs".to_string(),
+				result_hash: Some(true),
+				..Default::default()
+			};
+			glob_match_entries(entries, options)
+				.unwrap()
+				.result_hash
+				.unwrap()
+		};
+		let base = hash(vec![entry("a.rs", 1.0), entry("b.rs", 2.0)]);
+		assert_eq!(base, hash(vec![entry("a.rs", 1.0), entry("b.rs", 2.0)]));
+		assert_ne!(base, hash(vec![entry("a.rs", 1.0), entry("b.rs", 3.0)]));
+		assert_ne!(base, hash(vec![entry("a.rs", 1.0)]));
+	}
+
+	#[test]
+	fn test_glob_match_entries_child_count() {
+		let entries = vec![
+			entry("empty", FileType::Dir),
+			entry("src", FileType::Dir),
+			entry("src/a.rs", FileType::File),
+			entry("src/sub", FileType::Dir),
+			entry("src/sub/b.rs", FileType::File),
+		];
+		let options = GlobOptions {
+			pattern: "**/*".to_string(),
+			include_child_count: Some(true),
+			..Default::default()
+		};
+		let result = glob_match_entries(entries, options).unwrap();
+		let counts: Vec<_> = result
+			.matches
+			.into_iter()
+			.map(|entry| (entry.path, entry.child_count))
+			.collect();
+		assert_eq!(counts, [
+			("empty".to_string(), Some(0)),
+			("src".to_string(), Some(2)),
+			("src/a.rs".to_string(), None),
+			("src/sub".to_string(), Some(1)),
+			("src/sub/b.rs".to_string(), None),
+		]);
+	}
+
+	#[test]
+	fn test_glob_match_entries_order_index() {
+		let entries = vec![file("a.rs"), file("b.txt"), file("c.rs"), file("d.rs")];
+		let options = GlobOptions {
+			pattern: "*.rs".to_string(),
+			max_results: Some(2),
+			include_order_index: Some(true),
+			..Default::default()
+		};
+		let result = glob_match_entries(entries, options).unwrap();
+		let indexes: Vec<_> = result.matches.iter().map(|m| m.order_index).collect();
+		assert_eq!(indexes, [Some(0), Some(2)]);
+	}
+
+	#[test]
+	fn test_glob_match_entries_depth() {
+		let entries = vec![file("a.rs"), file("src/b.rs"), file("src/sub/c.rs")];
+		let options = GlobOptions {
+			pattern: "**/*.rs".to_string(),
+			include_depth: Some(true),
+			..Default::default()
+		};
+		let result = glob_match_entries(entries, options).unwrap();
+		let depths: Vec<_> = result.matches.iter().map(|m| m.depth).collect();
+		assert_eq!(depths, [Some(0), Some(1), Some(2)]);
+	}
+
+	#[test]
+	fn test_glob_match_entries_pattern_index() {
+		let entries = vec![file("a.rs"), file("b.txt"), file("src/c.rs")];
+		let indexes = |include_pattern_index| {
+			let options = GlobOptions {
+				pattern: "**/*.rs".to_string(),
+				include_pattern_index,
+				..Default::default()
+			};
+			let result = glob_match_entries(entries.clone(), options).unwrap();
+			result
+				.matches
+				.iter()
+				.map(|m| m.pattern_index)
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(indexes(Some(true)), [Some(0), Some(0)]);
+		assert_eq!(indexes(None), [None, None]);
+	}
+
+	#[test]
+	fn test_glob_match_entries_paths_format() {
+		let options = GlobOptions {
+			pattern: "*.rs".to_string(),
+			format: Some("paths".to_string()),
+			..Default::default()
+		};
+		let result =
+			glob_match_entries(vec![file("a.rs"), file("b.txt"), file("src/c.rs")], options).unwrap();
+		assert!(result.matches.is_empty());
+		assert_eq!(result.paths.as_deref(), Some("a.rs\nsrc/c.rs"));
+		assert_eq!(result.total_matches, 2);
+	}
+
+	#[test]
+	fn test_glob_match_entries_buffer_format() {
+		let entries = vec![
+			GlobMatch::new("a.rs".to_string(), FileType::File, Some(1.5)),
+			entry("src", FileType::Dir),
+		];
+		let options = GlobOptions {
+			pattern: "*".to_string(),
+			format: Some("buffer".to_string()),
+			..Default::default()
+		};
+		let result = glob_match_entries(entries, options).unwrap();
+		assert!(result.matches.is_empty());
+		let buffer = result.buffer.unwrap();
+		assert_eq!(buffer.len(), 2 * 13 + "a.rs".len() + "src".len());
+		assert_eq!(&buffer[..8], b"\x04\0\0\0a.rs");
+		assert_eq!(buffer[8], FileType::File as u8);
+		assert_eq!(buffer[9..17], 1.5f64.to_le_bytes());
+		assert_eq!(&buffer[17..24], b"\x03\0\0\0src");
+		assert_eq!(buffer[24], FileType::Dir as u8);
+		assert!(f64::from_le_bytes(buffer[25..33].try_into().unwrap()).is_nan());
+	}
+
+	#[test]
+	fn test_native_self_test() {
+		let report = native_self_test();
+		assert!(report.ok, "{:?}", report.error);
+		assert_eq!(report.walked_entries, 4);
+	}
+
+	#[cfg(windows)]
+	#[test]
+	fn test_build_glob_pattern_windows_always_normalizes() {
+		assert_eq!(build_glob_pattern("src\\*.rs", false, true), "src/*.rs");
+	}
+}
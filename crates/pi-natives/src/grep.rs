@@ -200,9 +200,14 @@ pub struct GrepResult {
 	pub limit_reached:      Option<bool>,
 }
 
-enum TypeFilter {
+/// File type/language filter shared by grep's `type` and glob's `language`.
+pub enum TypeFilter {
+	/// Built-in language: matching extensions and exact file names.
 	Known { exts: &'static [&'static str], names: &'static [&'static str] },
+	/// Unknown type name, treated as a single extension.
 	Custom(String),
+	/// Caller-supplied extension list.
+	Extensions(Vec<String>),
 }
 
 impl TypeFilter {
@@ -210,6 +215,7 @@ impl TypeFilter {
 		match self {
 			Self::Known { exts, .. } => exts.iter().any(|e| ext.eq_ignore_ascii_case(e)),
 			Self::Custom(custom_ext) => ext.eq_ignore_ascii_case(custom_ext),
+			Self::Extensions(exts) => exts.iter().any(|e| ext.eq_ignore_ascii_case(e)),
 		}
 	}
 
@@ -217,6 +223,7 @@ impl TypeFilter {
 		match self {
 			Self::Known { names, .. } => names.iter().any(|n| name.eq_ignore_ascii_case(n)),
 			Self::Custom(ext) => ext.eq_ignore_ascii_case(name),
+			Self::Extensions(_) => false,
 		}
 	}
 }
@@ -429,7 +436,8 @@ fn compile_glob(glob: Option<&str>) -> Result<Option<GlobSet>> {
 		.map_err(|err| Error::from_reason(format!("Failed to build glob matcher: {err}")))
 }
 
-fn resolve_type_filter(type_name: Option<&str>) -> Option<TypeFilter> {
+/// Map a type or language name (e.g. `"ts"`, `"rust"`) to its filter.
+pub fn resolve_type_filter(type_name: Option<&str>) -> Option<TypeFilter> {
 	let normalized = type_name
 		.map(str::trim)
 		.filter(|value| !value.is_empty())
@@ -471,7 +479,8 @@ fn resolve_type_filter(type_name: Option<&str>) -> Option<TypeFilter> {
 	Some(TypeFilter::Known { exts, names })
 }
 
-fn matches_type_filter(path: &Path, filter: &TypeFilter) -> bool {
+/// Whether `path`'s file name or extension is accepted by `filter`.
+pub fn matches_type_filter(path: &Path, filter: &TypeFilter) -> bool {
 	let base_name = path
 		.file_name()
 		.and_then(|name| name.to_str())
@@ -901,18 +910,43 @@ fn grep_sync(
 		});
 	}
 
+	let scan_options = fs_cache::ScanOptions::new(include_hidden, true);
 	let entries = if use_cache {
-		let scan = fs_cache::get_or_scan(&search_path, include_hidden, true, &ct)?;
+		let scan = fs_cache::get_or_scan(
+			&search_path,
+			scan_options,
+			&[],
+			fs_cache::SHARED_NAMESPACE,
+			None,
+			&ct,
+		)?;
 		let mut entries =
 			collect_files(&search_path, &scan.entries, glob_set.as_ref(), type_filter.as_ref());
 		if entries.is_empty() && scan.cache_age_ms >= fs_cache::empty_recheck_ms() {
-			let fresh = fs_cache::force_rescan(&search_path, include_hidden, true, true, &ct)?;
-			entries = collect_files(&search_path, &fresh, glob_set.as_ref(), type_filter.as_ref());
+			let fresh = fs_cache::force_rescan(
+				&search_path,
+				scan_options,
+				&[],
+				fs_cache::SHARED_NAMESPACE,
+				true,
+				None,
+				&ct,
+			)?;
+			entries =
+				collect_files(&search_path, &fresh.entries, glob_set.as_ref(), type_filter.as_ref());
 		}
 		entries
 	} else {
-		let fresh = fs_cache::force_rescan(&search_path, include_hidden, true, false, &ct)?;
-		collect_files(&search_path, &fresh, glob_set.as_ref(), type_filter.as_ref())
+		let fresh = fs_cache::force_rescan(
+			&search_path,
+			scan_options,
+			&[],
+			fs_cache::SHARED_NAMESPACE,
+			false,
+			None,
+			&ct,
+		)?;
+		collect_files(&search_path, &fresh.entries, glob_set.as_ref(), type_filter.as_ref())
 	};
 	// Check cancellation before heavy work
 	ct.heartbeat()?;
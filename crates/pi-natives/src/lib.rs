@@ -22,6 +22,7 @@
 #![allow(clippy::trivially_copy_pass_by_ref, reason = "napi env idiom")]
 
 pub mod clipboard;
+pub mod error;
 pub mod fd;
 pub mod fs_cache;
 pub mod glob;
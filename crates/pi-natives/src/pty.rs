@@ -6,74 +6,337 @@
 
 use std::{
 	collections::HashMap,
-	io::{Read, Write},
+	fs::File,
+	io::{BufWriter, Read, Write},
+	path::{Path, PathBuf},
 	str,
-	sync::{Arc, Mutex, mpsc},
-	time::Duration,
+	sync::{
+		Arc, LazyLock, Mutex,
+		atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering},
+		mpsc,
+	},
+	time::{Duration, Instant},
 };
 
+use dashmap::DashMap;
 use napi::{
 	bindgen_prelude::*,
 	threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode},
 };
 use napi_derive::napi;
-use portable_pty::{CommandBuilder, PtySize, native_pty_system};
+use portable_pty::{CommandBuilder, MasterPty, PtySize, native_pty_system};
 
-use crate::task;
+use crate::{
+	error::{self, ErrorCode},
+	task,
+};
 
 /// Options for running a command in a PTY session.
-#[napi(object)]
+#[napi(object, object_to_js = false)]
 pub struct PtyStartOptions<'env> {
-	/// Command string to execute.
-	pub command:    String,
-	/// Working directory for command execution.
-	pub cwd:        Option<String>,
+	/// Command string to execute through `sh -lc`. Exactly one of `command`
+	/// and `argv` must be set.
+	pub command:             Option<String>,
+	/// Program and arguments to execute directly, without a shell.
+	pub argv:                Option<Vec<String>>,
+	/// Working directory for command execution; relative to `baseDir` when
+	/// that is set.
+	pub cwd:                 Option<String>,
+	/// Directory `cwd` is resolved against. The canonicalized result must stay
+	/// inside it, or the run fails with `PATH_OUTSIDE_BASE`.
+	#[napi(js_name = "baseDir")]
+	pub base_dir:            Option<String>,
 	/// Environment variables for this command.
-	pub env:        Option<HashMap<String, String>>,
+	pub env:                 Option<HashMap<String, String>>,
 	/// Timeout in milliseconds before cancelling.
 	#[napi(js_name = "timeoutMs")]
-	pub timeout_ms: Option<u32>,
+	pub timeout_ms:          Option<u32>,
+	/// Cancel after this many milliseconds without `write()` input; counts as
+	/// a timeout. `inputScript` steps don't count as input. Combines with
+	/// `timeoutMs`.
+	#[napi(js_name = "idleTimeoutMs")]
+	pub idle_timeout_ms:     Option<u32>,
 	/// Abort signal for cancelling the operation.
-	pub signal:     Option<Unknown<'env>>,
+	pub signal:              Option<Unknown<'env>>,
 	/// PTY column count.
-	pub cols:       Option<u16>,
+	pub cols:                Option<u16>,
 	/// PTY row count.
-	pub rows:       Option<u16>,
+	pub rows:                Option<u16>,
+	/// File to write raw PTY output bytes to (truncated on start), in addition
+	/// to any chunk callback. A failed write rejects the run with `IO` once the
+	/// command exits.
+	#[napi(js_name = "outputFile")]
+	pub output_file:         Option<String>,
+	/// Delay between output/control polls in milliseconds (default: 16,
+	/// clamped to 1-250). Lower values cut latency at the cost of CPU wakeups.
+	#[napi(js_name = "pollIntervalMs")]
+	pub poll_interval_ms:    Option<u32>,
+	/// Handling of invalid UTF-8 in output chunks: `"replace"` (U+FFFD,
+	/// default), `"drop"`, or `"marker"` (emits `invalidUtf8Marker`). Other
+	/// values fail with `PTY_FAILURE`.
+	#[napi(js_name = "invalidUtf8")]
+	pub invalid_utf8:        Option<String>,
+	/// Text emitted per invalid sequence in `"marker"` mode (default: `"?"`).
+	#[napi(js_name = "invalidUtf8Marker")]
+	pub invalid_utf8_marker: Option<String>,
+	/// Route the command's stderr through a separate pipe instead of the PTY,
+	/// delivering it to `onChunk` tagged `"stderr"` (default: false). Needs
+	/// `command`, since the redirect runs in the shell. Unix only.
+	#[napi(js_name = "separateStderr")]
+	pub separate_stderr:     Option<bool>,
+	/// Called with the exit code as soon as the command exits, before trailing
+	/// output has been delivered.
+	#[napi(js_name = "onExit", ts_type = "((exitCode: number) => void) | undefined | null")]
+	pub on_exit:             Option<ThreadsafeFunction<i32>>,
+	/// Inputs written to the PTY in order once the command starts, each after
+	/// its delay; interleaves with `write()` calls.
+	#[napi(js_name = "inputScript")]
+	pub input_script:        Option<Vec<PtyInputStep>>,
+	/// Report the last non-blank output line as `lastLine` (default: false),
+	/// e.g. a build tool's final status. Escape sequences are kept as-is.
+	#[napi(js_name = "captureLastLine")]
+	pub capture_last_line:   Option<bool>,
+	/// Output decoding: `"utf8"` (default) or `"latin1"`, which maps each
+	/// byte to the code point of the same value for single-byte programs.
+	/// Other values fail with `PTY_FAILURE`.
+	pub encoding:            Option<String>,
+	/// Chunk delivered to `onChunk` before any command output, so parsers can
+	/// find where the command's output starts. Not written to `outputFile`.
+	#[napi(js_name = "markerPrefix")]
+	pub marker_prefix:       Option<String>,
+	/// Chunk delivered to `onChunk` after the command exits and all of its
+	/// output has been delivered, even when cancelled or timed out.
+	#[napi(js_name = "markerSuffix")]
+	pub marker_suffix:       Option<String>,
+	/// When the `argv` program isn't found on `PATH` (or at its path), resolve
+	/// with exit code 127 and a `<program>: command not found` chunk, like a
+	/// shell, instead of failing with `PTY_FAILURE` (default: false). Shell
+	/// `command`s already report a missing program as exit code 127.
+	#[napi(js_name = "notFoundAsExit")]
+	pub not_found_as_exit:   Option<bool>,
+}
+
+/// One timed step of a PTY `inputScript`.
+#[derive(Clone)]
+#[napi(object)]
+pub struct PtyInputStep {
+	/// Milliseconds to wait after the previous step (or the command start).
+	#[napi(js_name = "delayMs")]
+	pub delay_ms: u32,
+	/// Raw input written to PTY stdin.
+	pub data:     String,
 }
 
 /// Result of a PTY command run.
 #[napi(object)]
 pub struct PtyRunResult {
 	/// Exit code when the command completes.
-	pub exit_code: Option<i32>,
+	pub exit_code:     Option<i32>,
 	/// Whether command was cancelled by signal/user kill.
-	pub cancelled: bool,
+	pub cancelled:     bool,
 	/// Whether command timed out.
-	pub timed_out: bool,
+	pub timed_out:     bool,
+	/// Bytes written to `outputFile`, when one was requested.
+	pub bytes_written: Option<i64>,
+	/// Last non-blank output line, when `captureLastLine` was set.
+	pub last_line:     Option<String>,
+}
+
+/// Current dimensions of an active PTY.
+#[derive(Clone, Copy)]
+#[napi(object)]
+pub struct PtyDimensions {
+	/// PTY column count.
+	pub cols: u16,
+	/// PTY row count.
+	pub rows: u16,
 }
 
 #[derive(Clone)]
 struct PtyRunConfig {
 	command: String,
-	cwd:     Option<String>,
-	env:     Option<HashMap<String, String>>,
-	cols:    u16,
-	rows:    u16,
+	argv: Option<Vec<String>>,
+	cwd: Option<String>,
+	base_dir: Option<String>,
+	env: Option<HashMap<String, String>>,
+	cols: u16,
+	rows: u16,
+	output_file: Option<String>,
+	poll_interval: Duration,
+	/// Emitted in place of each invalid UTF-8 sequence; empty drops them.
+	invalid_utf8_replacement: String,
+	/// Decode output as Latin-1 rather than UTF-8.
+	latin1: bool,
+	separate_stderr: bool,
+	input_script: Vec<PtyInputStep>,
+	capture_last_line: bool,
+	marker_prefix: Option<String>,
+	marker_suffix: Option<String>,
+	not_found_as_exit: bool,
+}
+
+/// A running PTY command, as reported by `listPtySessions`.
+#[napi(object)]
+pub struct PtySessionInfo {
+	/// Process-unique id assigned when the command started.
+	pub id:             u32,
+	/// OS process id of the command, when known.
+	pub pid:            Option<u32>,
+	/// Shell command, or the space-joined `argv`.
+	pub command:        String,
+	/// Milliseconds since the command started.
+	pub age_ms:         u32,
+	/// Raw output bytes read from the PTY so far.
+	pub captured_bytes: i64,
+}
+
+/// Bookkeeping for a command that is currently running in some session.
+struct ActiveSession {
+	pid:        Option<u32>,
+	command:    String,
+	started_at: Instant,
+	output_len: Arc<AtomicU64>,
+}
+
+static ACTIVE_SESSIONS: LazyLock<DashMap<u32, ActiveSession>> = LazyLock::new(DashMap::new);
+static NEXT_SESSION_ID: AtomicU32 = AtomicU32::new(1);
+
+/// Removes its session from [`ACTIVE_SESSIONS`] when the run ends.
+struct SessionRegistration(u32);
+
+const DEFAULT_MAX_SESSIONS: usize = 128;
+
+/// Sessions holding a [`SessionPermit`].
+static OPEN_SESSIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Process-wide cap on concurrently started sessions, so a runaway caller
+/// cannot exhaust PTY devices and file descriptors.
+fn max_sessions() -> usize {
+	std::env::var("PTY_MAX_SESSIONS")
+		.ok()
+		.and_then(|value| value.parse().ok())
+		.unwrap_or(DEFAULT_MAX_SESSIONS)
+}
+
+/// A slot in the [`max_sessions`] budget, taken by `start()` before the PTY is
+/// opened and released when the run ends.
+struct SessionPermit;
+
+impl SessionPermit {
+	fn acquire(max: usize) -> Result<Self> {
+		OPEN_SESSIONS
+			.fetch_update(Ordering::AcqRel, Ordering::Acquire, |open| (open < max).then_some(open + 1))
+			.map(|_| Self)
+			.map_err(|open| {
+				error::coded(
+					ErrorCode::PtyLimitReached,
+					format!("PTY session limit reached: {open} of PTY_MAX_SESSIONS={max} in use"),
+				)
+			})
+	}
+}
+
+impl Drop for SessionPermit {
+	fn drop(&mut self) {
+		OPEN_SESSIONS.fetch_sub(1, Ordering::AcqRel);
+	}
+}
+
+impl ActiveSession {
+	fn register(
+		config: &PtyRunConfig,
+		pid: Option<u32>,
+		output_len: Arc<AtomicU64>,
+	) -> SessionRegistration {
+		let id = NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed);
+		let command = config
+			.argv
+			.as_ref()
+			.map_or_else(|| config.command.clone(), |argv| argv.join(" "));
+		ACTIVE_SESSIONS.insert(id, Self { pid, command, started_at: Instant::now(), output_len });
+		SessionRegistration(id)
+	}
+}
+
+impl Drop for SessionRegistration {
+	fn drop(&mut self) {
+		ACTIVE_SESSIONS.remove(&self.0);
+	}
+}
+
+/// List the commands currently running in any `PtySession`, oldest first.
+///
+/// Reads tracked state only; the running commands are unaffected.
+#[napi(js_name = "listPtySessions")]
+pub fn list_pty_sessions() -> Vec<PtySessionInfo> {
+	let mut sessions: Vec<PtySessionInfo> = ACTIVE_SESSIONS
+		.iter()
+		.map(|entry| {
+			let session = entry.value();
+			PtySessionInfo {
+				id:             *entry.key(),
+				pid:            session.pid,
+				command:        session.command.clone(),
+				age_ms:         session
+					.started_at
+					.elapsed()
+					.as_millis()
+					.min(u32::MAX as u128) as u32,
+				captured_bytes: session.output_len.load(Ordering::Relaxed) as i64,
+			}
+		})
+		.collect();
+	sessions.sort_by_key(|session| session.id);
+	sessions
+}
+
+/// Stream an output chunk came from, passed to `onChunk` after the text.
+#[napi(string_enum = "lowercase")]
+#[derive(Clone, Copy)]
+pub enum PtyStream {
+	/// Everything the command wrote to the terminal.
+	Stdout,
+	/// The command's stderr, with `separateStderr`.
+	Stderr,
+}
+
+/// Receives `(chunk, stream)` for each piece of decoded output.
+type ChunkCallback = ThreadsafeFunction<FnArgs<(String, PtyStream)>>;
+
+/// JS callbacks notified while a PTY command runs.
+struct PtyCallbacks {
+	output: Option<ChunkCallback>,
+	exit:   Option<ThreadsafeFunction<i32>>,
 }
 
 enum ReaderEvent {
 	Chunk(String),
+	StderrChunk(String),
 	Done,
+	StderrDone,
 }
 
 enum ControlMessage {
-	Input(String),
-	Resize { cols: u16, rows: u16 },
+	/// Raw stdin input; `scripted` marks `inputScript` steps, which don't
+	/// count as activity for `idleTimeoutMs`.
+	Input {
+		data:     String,
+		scripted: bool,
+	},
+	Resize {
+		cols: u16,
+		rows: u16,
+	},
+	Eof,
 	Kill,
 }
 
 struct PtySessionCore {
 	control_tx: mpsc::Sender<ControlMessage>,
+	/// Last size applied to the PTY master, shared with the run loop.
+	size:       Arc<Mutex<PtyDimensions>>,
+	/// Raw output bytes read from the PTY so far, shared with the reader.
+	output_len: Arc<AtomicU64>,
 }
 
 /// Stateful PTY session for interactive stdin/stdout passthrough.
@@ -96,97 +359,445 @@ impl PtySession {
 	}
 
 	/// Start a PTY command and stream output chunks via callback.
+	///
+	/// Each chunk is tagged with its [`PtyStream`]. Throws `PTY_FAILURE`
+	/// unless exactly one of `command` and `argv` is set (`separateStderr`
+	/// needs `command`), and `PTY_LIMIT_REACHED` when `PTY_MAX_SESSIONS`
+	/// (default 128) sessions are already running process-wide.
 	#[napi]
 	pub fn start<'env>(
 		&self,
 		env: &'env Env,
 		options: PtyStartOptions<'env>,
-		#[napi(ts_arg_type = "((chunk: string) => void) | undefined | null")] on_chunk: Option<
-			ThreadsafeFunction<String>,
-		>,
+		#[napi(ts_arg_type = "((chunk: string, stream: PtyStream) => void) | undefined | null")]
+		on_chunk: Option<ChunkCallback>,
 	) -> Result<PromiseRaw<'env, PtyRunResult>> {
+		if options.command.is_some() == options.argv.is_some() {
+			return Err(error::to_js_error(
+				env,
+				error::coded(ErrorCode::PtyFailure, "Exactly one of command or argv must be set"),
+			));
+		}
+		if options.argv.is_some() && options.separate_stderr == Some(true) {
+			return Err(error::to_js_error(
+				env,
+				error::coded(
+					ErrorCode::PtyFailure,
+					"separateStderr needs command; argv runs without a shell to redirect stderr",
+				),
+			));
+		}
 		let run_config = PtyRunConfig {
-			command: options.command,
-			cwd:     options.cwd,
-			env:     options.env,
-			cols:    options.cols.unwrap_or(120).clamp(20, 400),
-			rows:    options.rows.unwrap_or(40).clamp(5, 200),
+			command: options.command.unwrap_or_default(),
+			argv: options.argv,
+			cwd: options.cwd,
+			base_dir: options.base_dir,
+			env: options.env,
+			cols: options.cols.unwrap_or(120).clamp(20, 400),
+			rows: options.rows.unwrap_or(40).clamp(5, 200),
+			output_file: options.output_file,
+			poll_interval: Duration::from_millis(
+				options.poll_interval_ms.unwrap_or(16).clamp(1, 250).into(),
+			),
+			invalid_utf8_replacement: invalid_utf8_replacement(
+				options.invalid_utf8.as_deref(),
+				options.invalid_utf8_marker,
+			)
+			.map_err(|err| error::to_js_error(env, err))?,
+			latin1: is_latin1(options.encoding.as_deref())
+				.map_err(|err| error::to_js_error(env, err))?,
+			separate_stderr: options.separate_stderr.unwrap_or(false),
+			input_script: options.input_script.unwrap_or_default(),
+			capture_last_line: options.capture_last_line.unwrap_or(false),
+			marker_prefix: options.marker_prefix,
+			marker_suffix: options.marker_suffix,
+			not_found_as_exit: options.not_found_as_exit.unwrap_or(false),
 		};
-		let ct = task::CancelToken::new(options.timeout_ms, options.signal);
+		let callbacks = PtyCallbacks { output: on_chunk, exit: options.on_exit };
+		let mut ct = task::CancelToken::new(options.timeout_ms, options.signal);
+		if let Some(idle_timeout_ms) = options.idle_timeout_ms {
+			ct = ct.with_idle_timeout(idle_timeout_ms);
+		}
 		let core = Arc::clone(&self.core);
+		let size =
+			Arc::new(Mutex::new(PtyDimensions { cols: run_config.cols, rows: run_config.rows }));
+		let output_len = Arc::new(AtomicU64::new(0));
 
 		// Register control channel synchronously so write()/kill() work immediately.
 		let (control_tx, control_rx) = mpsc::channel::<ControlMessage>();
-		{
-			let mut guard = core
-				.lock()
-				.map_err(|_| Error::from_reason("PTY session lock poisoned"))?;
+		let script_tx = control_tx.clone();
+		let permit = {
+			let mut guard = core.lock().map_err(|_| {
+				error::to_js_error(
+					env,
+					error::coded(ErrorCode::PtyFailure, "PTY session lock poisoned"),
+				)
+			})?;
 			if guard.is_some() {
-				return Err(Error::from_reason("PTY session already running"));
+				return Err(error::to_js_error(
+					env,
+					error::coded(ErrorCode::PtyAlreadyRunning, "PTY session already running"),
+				));
 			}
-			*guard = Some(PtySessionCore { control_tx });
-		}
+			// Only a session that will actually start counts against the limit.
+			let permit =
+				SessionPermit::acquire(max_sessions()).map_err(|err| error::to_js_error(env, err))?;
+			*guard = Some(PtySessionCore {
+				control_tx,
+				size: Arc::clone(&size),
+				output_len: Arc::clone(&output_len),
+			});
+			permit
+		};
 		task::future(env, "pty.start", async move {
-			let run_result =
-				tokio::task::spawn_blocking(move || run_pty_sync(run_config, on_chunk, control_rx, ct))
-					.await;
+			let run_result = tokio::task::spawn_blocking(move || {
+				run_pty_sync(run_config, callbacks, script_tx, control_rx, &size, output_len, ct)
+			})
+			.await;
 
 			// Always clear core regardless of result
 			let mut guard = core
 				.lock()
-				.map_err(|_| Error::from_reason("PTY session lock poisoned"))?;
+				.map_err(|_| error::coded(ErrorCode::PtyFailure, "PTY session lock poisoned"))?;
 			*guard = None;
 			drop(guard);
+			drop(permit);
 
 			match run_result {
 				Ok(inner) => inner,
-				Err(err) => Err(Error::from_reason(format!("PTY execution task failed: {err}"))),
+				Err(err) => {
+					Err(error::coded(ErrorCode::PtyFailure, format!("PTY execution task failed: {err}")))
+				},
 			}
 		})
 	}
 
 	/// Write raw input bytes to PTY stdin.
 	#[napi]
-	pub fn write(&self, data: String) -> Result<()> {
-		self.send_control(ControlMessage::Input(data))
+	pub fn write(&self, env: &Env, data: String) -> Result<()> {
+		self.send_control(env, ControlMessage::Input { data, scripted: false })
 	}
 
 	/// Resize the active PTY.
 	#[napi]
-	pub fn resize(&self, cols: u16, rows: u16) -> Result<()> {
-		self.send_control(ControlMessage::Resize {
+	pub fn resize(&self, env: &Env, cols: u16, rows: u16) -> Result<()> {
+		self.send_control(env, ControlMessage::Resize {
 			cols: cols.clamp(20, 400),
 			rows: rows.clamp(5, 200),
 		})
 	}
 
+	/// Get the size last applied to the active PTY.
+	#[napi]
+	pub fn size(&self, env: &Env) -> Result<PtyDimensions> {
+		self
+			.current_size()
+			.map_err(|err| error::to_js_error(env, err))
+	}
+
+	/// Get the number of raw output bytes read from the active PTY so far.
+	#[napi(js_name = "capturedBytes")]
+	pub fn captured_bytes(&self, env: &Env) -> Result<i64> {
+		self
+			.with_core(|core| Ok(core.output_len.load(Ordering::Relaxed) as i64))
+			.map_err(|err| error::to_js_error(env, err))
+	}
+
+	/// Close PTY stdin so the command sees EOF; later writes are ignored.
+	#[napi(js_name = "sendEof")]
+	pub fn send_eof(&self, env: &Env) -> Result<()> {
+		self.send_control(env, ControlMessage::Eof)
+	}
+
 	/// Force-kill the active PTY command.
 	#[napi]
-	pub fn kill(&self) -> Result<()> {
-		self.send_control(ControlMessage::Kill)
+	pub fn kill(&self, env: &Env) -> Result<()> {
+		self.send_control(env, ControlMessage::Kill)
 	}
 }
 
 impl PtySession {
-	fn send_control(&self, message: ControlMessage) -> Result<()> {
+	fn with_core<T>(&self, f: impl FnOnce(&PtySessionCore) -> Result<T>) -> Result<T> {
 		let guard = self
 			.core
 			.lock()
-			.map_err(|_| Error::from_reason("PTY session lock poisoned"))?;
+			.map_err(|_| error::coded(ErrorCode::PtyFailure, "PTY session lock poisoned"))?;
 		let core = guard
 			.as_ref()
-			.ok_or_else(|| Error::from_reason("PTY session is not running"))?;
-		core
-			.control_tx
-			.send(message)
-			.map_err(|_| Error::from_reason("PTY session is no longer available"))
+			.ok_or_else(|| error::coded(ErrorCode::PtyNotRunning, "PTY session is not running"))?;
+		f(core)
+	}
+
+	fn current_size(&self) -> Result<PtyDimensions> {
+		self.with_core(|core| {
+			let size = core
+				.size
+				.lock()
+				.map_err(|_| error::coded(ErrorCode::PtyFailure, "PTY size lock poisoned"))?;
+			Ok(*size)
+		})
+	}
+
+	fn send_control(&self, env: &Env, message: ControlMessage) -> Result<()> {
+		self
+			.try_send_control(message)
+			.map_err(|err| error::to_js_error(env, err))
+	}
+
+	fn try_send_control(&self, message: ControlMessage) -> Result<()> {
+		self.with_core(|core| {
+			core.control_tx.send(message).map_err(|_| {
+				error::coded(ErrorCode::PtyNotRunning, "PTY session is no longer available")
+			})
+		})
+	}
+}
+
+/// Text emitted per invalid UTF-8 sequence for `invalidUtf8` mode `mode`.
+fn invalid_utf8_replacement(mode: Option<&str>, marker: Option<String>) -> Result<String> {
+	match mode {
+		None | Some("replace") => Ok("\u{FFFD}".to_string()),
+		Some("drop") => Ok(String::new()),
+		Some("marker") => Ok(marker.unwrap_or_else(|| "?".to_string())),
+		Some(other) => Err(error::coded(
+			ErrorCode::PtyFailure,
+			format!("Unknown invalidUtf8 mode {other:?}; expected replace, drop, or marker"),
+		)),
 	}
 }
 
+/// Whether `encoding` selects Latin-1 output decoding over UTF-8.
+fn is_latin1(encoding: Option<&str>) -> Result<bool> {
+	match encoding {
+		None | Some("utf8") => Ok(false),
+		Some("latin1") => Ok(true),
+		Some(other) => Err(error::coded(
+			ErrorCode::PtyFailure,
+			format!("Unknown encoding {other:?}; expected utf8 or latin1"),
+		)),
+	}
+}
+
+/// Reads `reader` to EOF, decoding UTF-8 incrementally.
+///
+/// Raw bytes go to `on_raw` before decoding; decoded text goes to `emit`, with
+/// each invalid sequence replaced by `replacement` (dropped when empty).
+fn pump_utf8(
+	mut reader: impl Read,
+	replacement: &str,
+	mut on_raw: impl FnMut(&[u8]),
+	mut emit: impl FnMut(String),
+) {
+	const BUF: usize = 4096;
+	let mut buf = [0u8; BUF + 4];
+	let mut it = 0;
+	loop {
+		match reader.read(&mut buf[it..BUF]) {
+			Ok(0) => {
+				break;
+			},
+			Ok(n) => {
+				on_raw(&buf[it..it + n]);
+				it += n;
+				while it > 0 {
+					let pending = &buf[..it];
+					match str::from_utf8(pending) {
+						Ok(text) => {
+							emit(text.to_string());
+							it = 0;
+							break;
+						},
+						Err(err) => {
+							let valid_up_to = err.valid_up_to();
+							if valid_up_to > 0 {
+								// SAFETY: [..valid_up_to] is guaranteed valid UTF-8 by valid_up_to().
+								let text = unsafe { str::from_utf8_unchecked(&pending[..valid_up_to]) };
+								emit(text.to_string());
+								buf.copy_within(valid_up_to..it, 0);
+								it -= valid_up_to;
+							}
+							match err.error_len() {
+								Some(invalid_len) => {
+									if !replacement.is_empty() {
+										emit(replacement.to_string());
+									}
+									buf.copy_within(invalid_len..it, 0);
+									it -= invalid_len;
+								},
+								None => {
+									break;
+								},
+							}
+						},
+					}
+				}
+			},
+			Err(_) => {
+				break;
+			},
+		}
+	}
+	for chunk in buf[..it].utf8_chunks() {
+		let valid = chunk.valid();
+		if !valid.is_empty() {
+			emit(valid.to_string());
+		}
+		if !chunk.invalid().is_empty() && !replacement.is_empty() {
+			emit(replacement.to_string());
+		}
+	}
+}
+
+/// Reads `reader` to EOF, decoding each byte as the Latin-1 code point of the
+/// same value. Lossless, so nothing needs replacing or carrying over.
+fn pump_latin1(mut reader: impl Read, mut on_raw: impl FnMut(&[u8]), mut emit: impl FnMut(String)) {
+	let mut buf = [0u8; 4096];
+	loop {
+		match reader.read(&mut buf) {
+			Ok(0) | Err(_) => {
+				break;
+			},
+			Ok(n) => {
+				on_raw(&buf[..n]);
+				emit(buf[..n].iter().copied().map(char::from).collect());
+			},
+		}
+	}
+}
+
+/// Reads `reader` to EOF with [`pump_latin1`] or [`pump_utf8`].
+fn pump_text(
+	reader: impl Read,
+	latin1: bool,
+	replacement: &str,
+	on_raw: impl FnMut(&[u8]),
+	emit: impl FnMut(String),
+) {
+	if latin1 {
+		pump_latin1(reader, on_raw, emit);
+	} else {
+		pump_utf8(reader, replacement, on_raw, emit);
+	}
+}
+
+/// Named pipe that a PTY command's stderr is redirected into.
+///
+/// The PTY layer closes inherited descriptors in the child, so stderr is
+/// routed by path: the shell runs `exec 2>"$PI_PTY_STDERR"` first. We hold a
+/// write end open until the command exits so the reader doesn't see EOF before
+/// the child opens the pipe.
+struct StderrFifo {
+	path:      PathBuf,
+	reader:    Option<File>,
+	keepalive: Option<File>,
+}
+
+impl StderrFifo {
+	const ENV_VAR: &str = "PI_PTY_STDERR";
+
+	#[cfg(unix)]
+	fn create() -> Result<Self> {
+		use std::{
+			ffi::CString,
+			fs::OpenOptions,
+			os::unix::{ffi::OsStrExt, fs::OpenOptionsExt},
+		};
+
+		let io_error = |err: std::io::Error| {
+			error::coded(ErrorCode::Io, format!("Failed to create stderr pipe: {err}"))
+		};
+		let nanos = std::time::SystemTime::now()
+			.duration_since(std::time::UNIX_EPOCH)
+			.map_or(0, |d| d.as_nanos());
+		let path = std::env::temp_dir().join(format!("pi-pty-stderr-{}-{nanos}", std::process::id()));
+		let c_path = CString::new(path.as_os_str().as_bytes())
+			.map_err(|err| error::coded(ErrorCode::Io, format!("Invalid stderr pipe path: {err}")))?;
+		// SAFETY: `c_path` is a valid NUL-terminated path.
+		if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+			return Err(io_error(std::io::Error::last_os_error()));
+		}
+		let mut fifo = Self { path, reader: None, keepalive: None };
+		// Non-blocking so opening doesn't wait for a writer; cleared below.
+		let reader = OpenOptions::new()
+			.read(true)
+			.custom_flags(libc::O_NONBLOCK)
+			.open(&fifo.path)
+			.map_err(io_error)?;
+		fifo.keepalive = Some(
+			OpenOptions::new()
+				.write(true)
+				.open(&fifo.path)
+				.map_err(io_error)?,
+		);
+		// SAFETY: `reader` owns a valid open descriptor.
+		unsafe {
+			let fd = std::os::fd::AsRawFd::as_raw_fd(&reader);
+			let flags = libc::fcntl(fd, libc::F_GETFL);
+			libc::fcntl(fd, libc::F_SETFL, flags & !libc::O_NONBLOCK);
+		}
+		fifo.reader = Some(reader);
+		Ok(fifo)
+	}
+
+	#[cfg(not(unix))]
+	fn create() -> Result<Self> {
+		Err(error::coded(ErrorCode::PtyFailure, "separateStderr is only supported on Unix"))
+	}
+
+	fn path(&self) -> &Path {
+		&self.path
+	}
+
+	fn take_reader(&mut self) -> Result<File> {
+		self
+			.reader
+			.take()
+			.ok_or_else(|| error::coded(ErrorCode::PtyFailure, "Stderr pipe reader already taken"))
+	}
+
+	fn close_keepalive(&mut self) {
+		self.keepalive = None;
+	}
+}
+
+impl Drop for StderrFifo {
+	fn drop(&mut self) {
+		let _ = std::fs::remove_file(&self.path);
+	}
+}
+
+/// Replays `script` as [`ControlMessage::Input`] on a dedicated thread, so the
+/// steps share the run loop's writer with `write()` calls.
+///
+/// The thread stops early once the run loop has gone away.
+fn spawn_input_script(script: Vec<PtyInputStep>, control_tx: mpsc::Sender<ControlMessage>) {
+	if script.is_empty() {
+		return;
+	}
+	std::thread::spawn(move || {
+		for step in script {
+			std::thread::sleep(Duration::from_millis(step.delay_ms.into()));
+			if control_tx
+				.send(ControlMessage::Input { data: step.data, scripted: true })
+				.is_err()
+			{
+				break;
+			}
+		}
+	});
+}
+
+/// How long stderr may stay open after the command exited and the PTY
+/// reader finished.
+const STDERR_DRAIN_TIMEOUT: Duration = Duration::from_millis(500);
+
 fn run_pty_sync(
 	config: PtyRunConfig,
-	on_chunk: Option<ThreadsafeFunction<String>>,
+	callbacks: PtyCallbacks,
+	script_tx: mpsc::Sender<ControlMessage>,
 	control_rx: mpsc::Receiver<ControlMessage>,
+	size: &Mutex<PtyDimensions>,
+	output_len: Arc<AtomicU64>,
 	ct: task::CancelToken,
 ) -> Result<PtyRunResult> {
 	let pty_system = native_pty_system();
@@ -197,12 +808,68 @@ fn run_pty_sync(
 			pixel_width:  0,
 			pixel_height: 0,
 		})
-		.map_err(|err| Error::from_reason(format!("Failed to open PTY: {err}")))?;
+		.map_err(|err| error::coded(ErrorCode::PtyFailure, format!("Failed to open PTY: {err}")))?;
+
+	// Everything that can fail is set up before spawning, so an error never
+	// leaves the command running unsupervised.
+	let mut output_file = config
+		.output_file
+		.as_ref()
+		.map(|path| {
+			File::create(path).map(BufWriter::new).map_err(|err| {
+				error::coded(ErrorCode::Io, format!("Failed to open PTY output file: {err}"))
+			})
+		})
+		.transpose()?;
+	let writes_output = output_file.is_some();
+
+	let mut stderr_fifo = if config.separate_stderr {
+		Some(StderrFifo::create()?)
+	} else {
+		None
+	};
+	let stderr_reader = stderr_fifo
+		.as_mut()
+		.map(StderrFifo::take_reader)
+		.transpose()?;
 
-	let mut cmd = CommandBuilder::new("sh");
-	cmd.arg("-lc");
-	cmd.arg(&config.command);
-	if let Some(cwd) = config.cwd.as_ref() {
+	let master = pair.master;
+	// Dropping the writer closes stdin (the PTY layer sends the EOF character).
+	let mut writer = Some(master.take_writer().map_err(|err| {
+		error::coded(ErrorCode::PtyFailure, format!("Failed to create PTY writer: {err}"))
+	})?);
+	let reader = master.try_clone_reader().map_err(|err| {
+		error::coded(ErrorCode::PtyFailure, format!("Failed to create PTY reader: {err}"))
+	})?;
+
+	let mut cmd = match (&config.argv, &stderr_fifo) {
+		(Some(argv), _) if argv.is_empty() => {
+			return Err(error::coded(ErrorCode::PtyFailure, "argv must name a program"));
+		},
+		// `start()` rejects `separateStderr` with argv, so there is no pipe here.
+		(Some(argv), _) => {
+			let mut cmd = CommandBuilder::new(&argv[0]);
+			cmd.args(&argv[1..]);
+			cmd
+		},
+		(None, Some(_)) => {
+			let mut cmd = CommandBuilder::new("sh");
+			cmd.arg("-lc");
+			cmd.arg(format!("exec 2>\"${}\"\n{}", StderrFifo::ENV_VAR, config.command));
+			cmd
+		},
+		(None, None) => {
+			let mut cmd = CommandBuilder::new("sh");
+			cmd.arg("-lc");
+			cmd.arg(&config.command);
+			cmd
+		},
+	};
+	if let Some(fifo) = &stderr_fifo {
+		cmd.env(StderrFifo::ENV_VAR, fifo.path());
+	}
+	let cwd = resolve_cwd(config.cwd.as_deref(), config.base_dir.as_deref())?;
+	if let Some(cwd) = &cwd {
 		cmd.cwd(cwd);
 	}
 	if let Some(env) = config.env.as_ref() {
@@ -211,102 +878,129 @@ fn run_pty_sync(
 		}
 	}
 
-	let mut child = pair
-		.slave
-		.spawn_command(cmd)
-		.map_err(|err| Error::from_reason(format!("Failed to spawn PTY command: {err}")))?;
+	let mut child = match pair.slave.spawn_command(cmd) {
+		Ok(child) => child,
+		Err(err)
+			if config.not_found_as_exit
+				&& let Some(program) = config.argv.as_ref().and_then(|argv| argv.first())
+				&& is_not_found(&*err) =>
+		{
+			return Ok(command_not_found(program, &config, &callbacks));
+		},
+		Err(err) => {
+			return Err(error::coded(
+				ErrorCode::PtyFailure,
+				format!("Failed to spawn PTY command: {err}"),
+			));
+		},
+	};
 	drop(pair.slave);
-
-	let master = pair.master;
-	let mut writer = master
-		.take_writer()
-		.map_err(|err| Error::from_reason(format!("Failed to create PTY writer: {err}")))?;
-	let mut reader = master
-		.try_clone_reader()
-		.map_err(|err| Error::from_reason(format!("Failed to create PTY reader: {err}")))?;
+	let _registration =
+		ActiveSession::register(&config, child.process_id(), Arc::clone(&output_len));
+	spawn_input_script(config.input_script.clone(), script_tx);
+	// Emitted from this thread before the reader starts, so it precedes every
+	// output chunk.
+	if let Some(marker) = &config.marker_prefix {
+		emit_chunk(marker, PtyStream::Stdout, callbacks.output.as_ref());
+	}
 
 	let (reader_tx, reader_rx) = mpsc::channel::<ReaderEvent>();
+	let replacement = config.invalid_utf8_replacement.clone();
+	let latin1 = config.latin1;
+	let stdout_tx = reader_tx.clone();
 	let reader_thread = std::thread::spawn(move || {
-		const REPLACEMENT: &str = "\u{FFFD}";
-		const BUF: usize = 4096;
-		let mut buf = [0u8; BUF + 4];
-		let mut it = 0;
-		loop {
-			match reader.read(&mut buf[it..BUF]) {
-				Ok(0) => {
-					break;
-				},
-				Ok(n) => {
-					it += n;
-					while it > 0 {
-						let pending = &buf[..it];
-						match str::from_utf8(pending) {
-							Ok(text) => {
-								let _ = reader_tx.send(ReaderEvent::Chunk(text.to_string()));
-								it = 0;
-								break;
-							},
-							Err(err) => {
-								let valid_up_to = err.valid_up_to();
-								if valid_up_to > 0 {
-									// SAFETY: [..valid_up_to] is guaranteed valid UTF-8 by valid_up_to().
-									let text = unsafe { str::from_utf8_unchecked(&pending[..valid_up_to]) };
-									let _ = reader_tx.send(ReaderEvent::Chunk(text.to_string()));
-									buf.copy_within(valid_up_to..it, 0);
-									it -= valid_up_to;
-								}
-								match err.error_len() {
-									Some(invalid_len) => {
-										let _ = reader_tx.send(ReaderEvent::Chunk(REPLACEMENT.to_string()));
-										buf.copy_within(invalid_len..it, 0);
-										it -= invalid_len;
-									},
-									None => {
-										break;
-									},
-								}
-							},
-						}
+		let mut bytes_written = 0u64;
+		// The first failed write stops the tee; the run rejects with it once
+		// the command is done.
+		let mut write_error = None;
+		pump_text(
+			reader,
+			latin1,
+			&replacement,
+			|raw| {
+				output_len.fetch_add(raw.len() as u64, Ordering::Relaxed);
+				if let Some(file) = output_file.as_mut() {
+					// Tee raw bytes before decoding so the file is byte-exact.
+					match file.write_all(raw) {
+						Ok(()) => bytes_written += raw.len() as u64,
+						Err(err) => {
+							write_error = Some(err);
+							output_file = None;
+						},
 					}
-				},
-				Err(_) => {
-					break;
-				},
-			}
-		}
-		for chunk in buf[..it].utf8_chunks() {
-			let valid = chunk.valid();
-			if !valid.is_empty() {
-				let _ = reader_tx.send(ReaderEvent::Chunk(valid.to_string()));
-			}
-			if !chunk.invalid().is_empty() {
-				let _ = reader_tx.send(ReaderEvent::Chunk(REPLACEMENT.to_string()));
-			}
+				}
+			},
+			|text| {
+				let _ = stdout_tx.send(ReaderEvent::Chunk(text));
+			},
+		);
+		if let Some(mut file) = output_file
+			&& let Err(err) = file.flush()
+		{
+			write_error.get_or_insert(err);
 		}
-		let _ = reader_tx.send(ReaderEvent::Done);
+		// Sent after `pump_text`'s final flush, so `Done` trails every chunk.
+		let _ = stdout_tx.send(ReaderEvent::Done);
+		(bytes_written, write_error)
 	});
 
+	let mut stderr_done = stderr_reader.is_none();
+	if let Some(reader) = stderr_reader {
+		let replacement = config.invalid_utf8_replacement.clone();
+		std::thread::spawn(move || {
+			pump_text(
+				reader,
+				latin1,
+				&replacement,
+				|_| {},
+				|text| {
+					let _ = reader_tx.send(ReaderEvent::StderrChunk(text));
+				},
+			);
+			let _ = reader_tx.send(ReaderEvent::StderrDone);
+		});
+	} else {
+		drop(reader_tx);
+	}
+
 	let mut timed_out = false;
 	let mut cancelled = false;
 	let mut reader_done = false;
 	let mut exit_code: Option<i32> = None;
+	let mut last_line = config.capture_last_line.then(LastLine::default);
+	let mut stderr_deadline: Option<Instant> = None;
 
-	while exit_code.is_none() || !reader_done {
+	while exit_code.is_none() || !reader_done || !stderr_done {
 		if let Err(err) = ct.heartbeat() {
-			let message = err.to_string();
-			timed_out = message.contains("Timeout");
+			timed_out = error::code_of(&err) == Some(ErrorCode::Timeout);
 			cancelled = !timed_out;
 			let _ = child.kill();
 		}
 
 		loop {
 			match control_rx.try_recv() {
-				Ok(ControlMessage::Input(data)) => {
-					let _ = writer.write_all(data.as_bytes());
-					let _ = writer.flush();
+				Ok(ControlMessage::Input { data, scripted }) => {
+					if !scripted {
+						ct.reset_idle();
+					}
+					if let Some(writer) = writer.as_mut() {
+						let _ = writer.write_all(data.as_bytes());
+						let _ = writer.flush();
+					}
+				},
+				Ok(ControlMessage::Eof) => {
+					writer = None;
 				},
 				Ok(ControlMessage::Resize { cols, rows }) => {
-					let _ = master.resize(PtySize { rows, cols, pixel_width: 0, pixel_height: 0 });
+					if master
+						.resize(PtySize { rows, cols, pixel_width: 0, pixel_height: 0 })
+						.is_ok()
+					{
+						notify_resize(&*master, child.process_id());
+						if let Ok(mut size) = size.lock() {
+							*size = PtyDimensions { cols, rows };
+						}
+					}
 				},
 				Ok(ControlMessage::Kill) => {
 					cancelled = true;
@@ -317,48 +1011,410 @@ fn run_pty_sync(
 			}
 		}
 
-		loop {
-			match reader_rx.try_recv() {
-				Ok(ReaderEvent::Chunk(chunk)) => emit_chunk(&chunk, on_chunk.as_ref()),
-				Ok(ReaderEvent::Done) => {
-					reader_done = true;
-					break;
-				},
-				Err(mpsc::TryRecvError::Empty) => break,
-				Err(mpsc::TryRecvError::Disconnected) => {
-					reader_done = true;
-					break;
-				},
-			}
-		}
+		drain_reader_events(&reader_rx, &mut reader_done, &mut stderr_done, |event| match event {
+			ReaderEvent::Chunk(chunk) => {
+				if let Some(last_line) = last_line.as_mut() {
+					last_line.push(&chunk);
+				}
+				emit_chunk(&chunk, PtyStream::Stdout, callbacks.output.as_ref());
+			},
+			ReaderEvent::StderrChunk(chunk) => {
+				emit_chunk(&chunk, PtyStream::Stderr, callbacks.output.as_ref());
+			},
+			ReaderEvent::Done | ReaderEvent::StderrDone => {},
+		});
 
 		if exit_code.is_none()
-			&& let Some(status) = child
-				.try_wait()
-				.map_err(|err| Error::from_reason(format!("Failed checking PTY status: {err}")))?
+			&& let Some(status) = child.try_wait().map_err(|err| {
+				let _ = child.kill();
+				let _ = child.wait();
+				error::coded(ErrorCode::PtyFailure, format!("Failed checking PTY status: {err}"))
+			})? {
+			let code = i32::try_from(status.exit_code()).unwrap_or(i32::MAX);
+			exit_code = Some(code);
+			if let Some(callback) = callbacks.exit.as_ref() {
+				// Trailing output may still be queued; the result waits for it.
+				callback.call(Ok(code), ThreadsafeFunctionCallMode::NonBlocking);
+			}
+		}
+		if exit_code.is_some()
+			&& let Some(fifo) = stderr_fifo.as_mut()
 		{
-			exit_code = Some(i32::try_from(status.exit_code()).unwrap_or(i32::MAX));
+			// Let the stderr reader see EOF once the command's writers are gone.
+			fifo.close_keepalive();
+		}
+		if exit_code.is_some() && reader_done && !stderr_done {
+			// A background child that inherited stderr keeps the pipe open, so
+			// stop waiting for its EOF a grace period after the PTY drains.
+			let deadline =
+				*stderr_deadline.get_or_insert_with(|| Instant::now() + STDERR_DRAIN_TIMEOUT);
+			if Instant::now() >= deadline {
+				stderr_done = true;
+			}
 		}
 
-		if exit_code.is_none() || !reader_done {
-			std::thread::sleep(Duration::from_millis(16));
+		if exit_code.is_none() || !reader_done || !stderr_done {
+			std::thread::sleep(config.poll_interval);
 		}
 	}
 
 	if exit_code.is_none() {
-		let status = child
-			.wait()
-			.map_err(|err| Error::from_reason(format!("Failed waiting PTY process: {err}")))?;
+		let status = child.wait().map_err(|err| {
+			error::coded(ErrorCode::PtyFailure, format!("Failed waiting PTY process: {err}"))
+		})?;
 		exit_code = Some(i32::try_from(status.exit_code()).unwrap_or(i32::MAX));
 	}
+	if let Some(marker) = &config.marker_suffix {
+		emit_chunk(marker, PtyStream::Stdout, callbacks.output.as_ref());
+	}
+
+	let (bytes_written, write_error) = reader_thread.join().unwrap_or((0, None));
+	if let Some(err) = write_error {
+		return Err(error::coded(ErrorCode::Io, format!("Failed writing PTY output file: {err}")));
+	}
+
+	Ok(PtyRunResult {
+		exit_code,
+		cancelled,
+		timed_out,
+		bytes_written: writes_output.then_some(bytes_written as i64),
+		last_line: last_line.and_then(LastLine::finish),
+	})
+}
+
+/// Whether spawning failed because the program doesn't exist.
+///
+/// On Unix the PTY layer looks the program up itself before spawning and
+/// reports a failed lookup only as a message, so those messages count along
+/// with an [`io::ErrorKind::NotFound`](std::io::ErrorKind::NotFound) from the
+/// spawn. A program found but not executable is not "not found".
+fn is_not_found(err: &(dyn std::error::Error + 'static)) -> bool {
+	let io_not_found = std::iter::successors(Some(err), |err| err.source()).any(|err| {
+		err.downcast_ref::<std::io::Error>()
+			.is_some_and(|err| err.kind() == std::io::ErrorKind::NotFound)
+	});
+	let message = err.to_string();
+	io_not_found
+		|| message.contains("does not exist")
+		|| message.contains("doesn't exist")
+		|| (message.contains("No viable candidates") && !message.contains("exists but"))
+}
+
+/// Reports a program that doesn't exist the way a shell would: a `command
+/// not found` chunk between the markers, then exit code 127.
+fn command_not_found(
+	program: &str,
+	config: &PtyRunConfig,
+	callbacks: &PtyCallbacks,
+) -> PtyRunResult {
+	let message = format!("{program}: command not found");
+	if let Some(marker) = &config.marker_prefix {
+		emit_chunk(marker, PtyStream::Stdout, callbacks.output.as_ref());
+	}
+	emit_chunk(&format!("{message}\r\n"), PtyStream::Stdout, callbacks.output.as_ref());
+	if let Some(marker) = &config.marker_suffix {
+		emit_chunk(marker, PtyStream::Stdout, callbacks.output.as_ref());
+	}
+	if let Some(callback) = callbacks.exit.as_ref() {
+		callback.call(Ok(127), ThreadsafeFunctionCallMode::NonBlocking);
+	}
+	PtyRunResult {
+		exit_code:     Some(127),
+		cancelled:     false,
+		timed_out:     false,
+		// The output file was already created, and stays empty.
+		bytes_written: config.output_file.is_some().then_some(0),
+		last_line:     config.capture_last_line.then_some(message),
+	}
+}
+
+/// Sends `SIGWINCH` to the PTY's foreground process group (or the command's
+/// own group) after a resize.
+///
+/// The kernel only signals on an actual size change, so programs like `less`
+/// or `vim` could otherwise keep stale dimensions until their next redraw.
+#[cfg(unix)]
+fn notify_resize(master: &dyn MasterPty, pid: Option<u32>) {
+	// The command is a session leader, so its pid is also its group id.
+	let group = master
+		.process_group_leader()
+		.or_else(|| pid.and_then(|pid| libc::pid_t::try_from(pid).ok()));
+	if let Some(group) = group {
+		// SAFETY: `killpg` only sends a signal; a group that already exited
+		// yields `ESRCH`, which is ignored.
+		unsafe {
+			libc::killpg(group, libc::SIGWINCH);
+		}
+	}
+}
 
-	let _ = reader_thread.join();
+#[cfg(not(unix))]
+fn notify_resize(_master: &dyn MasterPty, _pid: Option<u32>) {}
+
+/// Delivers every pending reader event to `on_event`, recording stream
+/// completion in `reader_done`/`stderr_done`.
+///
+/// Each reader sends its `Done` only after its last chunk, so once both flags
+/// are set no output remains in the channel. A disconnected channel counts as
+/// both streams being done.
+fn drain_reader_events(
+	reader_rx: &mpsc::Receiver<ReaderEvent>,
+	reader_done: &mut bool,
+	stderr_done: &mut bool,
+	mut on_event: impl FnMut(ReaderEvent),
+) {
+	loop {
+		match reader_rx.try_recv() {
+			Ok(event) => {
+				match event {
+					ReaderEvent::Done => *reader_done = true,
+					ReaderEvent::StderrDone => *stderr_done = true,
+					ReaderEvent::Chunk(_) | ReaderEvent::StderrChunk(_) => {},
+				}
+				on_event(event);
+			},
+			Err(mpsc::TryRecvError::Empty) => break,
+			Err(mpsc::TryRecvError::Disconnected) => {
+				*reader_done = true;
+				*stderr_done = true;
+				break;
+			},
+		}
+	}
+}
 
-	Ok(PtyRunResult { exit_code, cancelled, timed_out })
+/// Tracks the most recent non-blank output line for `captureLastLine`.
+#[derive(Default)]
+struct LastLine {
+	/// Text after the last newline.
+	partial: String,
+	last:    Option<String>,
 }
 
-fn emit_chunk(text: &str, callback: Option<&ThreadsafeFunction<String>>) {
+impl LastLine {
+	/// Keeps at most this much of a line, so output without newlines stays
+	/// bounded.
+	const MAX_BYTES: usize = 4096;
+
+	fn push(&mut self, chunk: &str) {
+		let mut lines = chunk.split('\n');
+		if let Some(head) = lines.next() {
+			self.partial.push_str(head);
+		}
+		for line in lines {
+			let complete = std::mem::replace(&mut self.partial, line.to_string());
+			self.record(&complete);
+		}
+		if self.partial.len() > Self::MAX_BYTES {
+			let mut cut = self.partial.len() - Self::MAX_BYTES;
+			while !self.partial.is_char_boundary(cut) {
+				cut += 1;
+			}
+			self.partial.drain(..cut);
+		}
+	}
+
+	fn record(&mut self, line: &str) {
+		// PTYs end lines with `\r\n`; a bare `\r` redraws the line, so only the
+		// text after it is visible.
+		let line = line.trim_end_matches('\r');
+		let visible = line.rsplit('\r').next().unwrap_or(line);
+		if !visible.trim().is_empty() {
+			self.last = Some(visible.to_string());
+		}
+	}
+
+	/// The last line, counting unterminated trailing output as a line.
+	fn finish(mut self) -> Option<String> {
+		let partial = std::mem::take(&mut self.partial);
+		self.record(&partial);
+		self.last
+	}
+}
+
+fn emit_chunk(text: &str, stream: PtyStream, callback: Option<&ChunkCallback>) {
 	if let Some(callback) = callback {
-		callback.call(Ok(text.to_string()), ThreadsafeFunctionCallMode::NonBlocking);
+		callback.call(Ok((text.to_string(), stream).into()), ThreadsafeFunctionCallMode::NonBlocking);
+	}
+}
+
+/// Resolves the working directory, joining `cwd` onto `base_dir` when set.
+///
+/// With a base, both paths are canonicalized (following `..` and symlinks) and
+/// the result must lie inside the base. Without one, `cwd` is passed through.
+fn resolve_cwd(cwd: Option<&str>, base_dir: Option<&str>) -> Result<Option<PathBuf>> {
+	let Some(base_dir) = base_dir else {
+		return Ok(cwd.map(PathBuf::from));
+	};
+	let canonicalize = |path: &Path| {
+		std::fs::canonicalize(path).map_err(|err| {
+			error::coded(
+				ErrorCode::PathNotFound,
+				format!("Cannot resolve working directory {}: {err}", path.display()),
+			)
+		})
+	};
+	let base = canonicalize(Path::new(base_dir))?;
+	let resolved = canonicalize(&base.join(cwd.unwrap_or(".")))?;
+	if !resolved.starts_with(&base) {
+		return Err(error::coded(
+			ErrorCode::PathOutsideBase,
+			format!("cwd {} escapes baseDir {}", resolved.display(), base.display()),
+		));
+	}
+	if !resolved.is_dir() {
+		return Err(error::coded(
+			ErrorCode::NotADirectory,
+			format!("cwd {} is not a directory", resolved.display()),
+		));
+	}
+	Ok(Some(resolved))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fs_cache::tests::TempDir;
+
+	/// Yields one byte per read, splitting multi-byte characters across reads.
+	struct ByteReader<'a>(&'a [u8]);
+
+	impl Read for ByteReader<'_> {
+		fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+			let Some((&first, rest)) = self.0.split_first() else {
+				return Ok(0);
+			};
+			buf[0] = first;
+			self.0 = rest;
+			Ok(1)
+		}
+	}
+
+	#[test]
+	fn test_pump_utf8_flushes_trailing_bytes() {
+		let mut text = String::new();
+		let mut raw = 0;
+		pump_utf8(
+			ByteReader("héllo ✓\n".as_bytes()),
+			"?",
+			|bytes| raw += bytes.len(),
+			|chunk| text.push_str(&chunk),
+		);
+		assert_eq!(text, "héllo ✓\n");
+		assert_eq!(raw, "héllo ✓\n".len());
+
+		// A truncated sequence at EOF is still emitted, as the replacement.
+		let mut text = String::new();
+		pump_utf8(&b"done\xe2\x9c"[..], "?", |_| {}, |chunk| text.push_str(&chunk));
+		assert_eq!(text, "done?");
+	}
+
+	#[test]
+	fn test_pump_latin1_maps_bytes_to_code_points() {
+		let mut text = String::new();
+		pump_text(&b"caf\xe9 \x80\xff"[..], true, "?", |_| {}, |chunk| text.push_str(&chunk));
+		assert_eq!(text, "caf\u{e9} \u{80}\u{ff}");
+	}
+
+	#[test]
+	fn test_drain_reader_events_delivers_output_before_done() {
+		let (tx, rx) = mpsc::channel();
+		let writer = std::thread::spawn(move || {
+			for index in 0..100 {
+				tx.send(ReaderEvent::Chunk(index.to_string())).unwrap();
+			}
+			tx.send(ReaderEvent::Done).unwrap();
+		});
+		let mut output = Vec::new();
+		let mut reader_done = false;
+		let mut stderr_done = true;
+		while !reader_done {
+			drain_reader_events(&rx, &mut reader_done, &mut stderr_done, |event| {
+				if let ReaderEvent::Chunk(chunk) = event {
+					output.push(chunk);
+				}
+			});
+		}
+		writer.join().unwrap();
+		let expected: Vec<_> = (0..100).map(|index: i32| index.to_string()).collect();
+		assert_eq!(output, expected);
+	}
+
+	#[test]
+	fn test_resolve_cwd_stays_inside_base() {
+		let dir = TempDir::new("pty-cwd");
+		std::fs::create_dir_all(dir.join("base/sub")).unwrap();
+		std::fs::create_dir_all(dir.join("outside")).unwrap();
+		let base = dir.join("base");
+		let base_str = base.to_str().unwrap();
+		let canonical_base = std::fs::canonicalize(&base).unwrap();
+
+		let resolved = resolve_cwd(Some("sub/../sub"), Some(base_str)).unwrap();
+		assert_eq!(resolved, Some(canonical_base.join("sub")));
+		assert_eq!(resolve_cwd(None, Some(base_str)).unwrap(), Some(canonical_base));
+		let escaped = resolve_cwd(Some("../outside"), Some(base_str)).unwrap_err();
+		assert_eq!(error::code_of(&escaped), Some(ErrorCode::PathOutsideBase));
+		let missing = resolve_cwd(Some("missing"), Some(base_str)).unwrap_err();
+		assert_eq!(error::code_of(&missing), Some(ErrorCode::PathNotFound));
+		assert_eq!(resolve_cwd(Some("rel"), None).unwrap(), Some(PathBuf::from("rel")));
+	}
+
+	#[test]
+	fn test_session_permit_enforces_limit() {
+		let max = OPEN_SESSIONS.load(Ordering::Acquire) + 1;
+		let permit = SessionPermit::acquire(max).unwrap();
+		let err = SessionPermit::acquire(max).err().unwrap();
+		assert_eq!(error::code_of(&err), Some(ErrorCode::PtyLimitReached));
+		drop(permit);
+		assert!(SessionPermit::acquire(max).is_ok());
+	}
+
+	#[test]
+	fn test_last_line_tracks_complete_lines() {
+		let mut last_line = LastLine::default();
+		for chunk in ["Compiling\r\n50%\r100", "%\r\n\r\n", "Build succ", "eeded\r\n\r\n"] {
+			last_line.push(chunk);
+		}
+		assert_eq!(last_line.finish().as_deref(), Some("Build succeeded"));
+
+		let mut last_line = LastLine::default();
+		last_line.push("done\n$ ");
+		assert_eq!(last_line.finish().as_deref(), Some("$ "));
+		assert_eq!(LastLine::default().finish(), None);
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn test_is_not_found_classifies_spawn_errors() {
+		let spawn_error = |program: &str| {
+			let pair = native_pty_system().openpty(PtySize::default()).unwrap();
+			pair
+				.slave
+				.spawn_command(CommandBuilder::new(program))
+				.err()
+				.unwrap()
+		};
+		assert!(is_not_found(&*spawn_error("pi-natives-missing-program")));
+		assert!(is_not_found(&*spawn_error("/pi-natives-missing-dir/program")));
+		assert!(!is_not_found(&*spawn_error("/etc/passwd")));
+		assert!(is_not_found(&std::io::Error::from(std::io::ErrorKind::NotFound)));
+	}
+
+	#[test]
+	fn test_invalid_utf8_replacement_rejects_unknown_modes() {
+		assert_eq!(invalid_utf8_replacement(None, None).unwrap(), "\u{FFFD}");
+		assert_eq!(invalid_utf8_replacement(Some("drop"), None).unwrap(), "");
+		assert_eq!(invalid_utf8_replacement(Some("marker"), Some("!".into())).unwrap(), "!");
+		let err = invalid_utf8_replacement(Some("skip"), None).unwrap_err();
+		assert_eq!(error::code_of(&err), Some(ErrorCode::PtyFailure));
+	}
+
+	#[test]
+	fn test_is_latin1_rejects_unknown_encodings() {
+		assert!(!is_latin1(None).unwrap());
+		assert!(!is_latin1(Some("utf8")).unwrap());
+		assert!(is_latin1(Some("latin1")).unwrap());
+		let err = is_latin1(Some("cp1252")).unwrap_err();
+		assert_eq!(error::code_of(&err), Some(ErrorCode::PtyFailure));
 	}
 }
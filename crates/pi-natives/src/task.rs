@@ -31,7 +31,7 @@ use std::{
 	future::Future,
 	sync::{
 		Arc, Weak,
-		atomic::{AtomicU8, Ordering},
+		atomic::{AtomicU8, AtomicU64, Ordering},
 	},
 	time::{Duration, Instant},
 };
@@ -39,7 +39,10 @@ use std::{
 use napi::{Env, Error, Result, Task, bindgen_prelude::*};
 use tokio::sync::Notify;
 
-use crate::prof::profile_region;
+use crate::{
+	error::{self, ErrorCode},
+	prof::profile_region,
+};
 
 // ─────────────────────────────────────────────────────────────────────────────
 // Cancellation
@@ -100,13 +103,37 @@ impl Flag {
 	}
 }
 
+/// Inactivity deadline that moves forward on each [`CancelToken::reset_idle`].
+struct IdleTimer {
+	timeout:        Duration,
+	origin:         Instant,
+	/// Milliseconds after `origin` of the last recorded activity.
+	last_active_ms: AtomicU64,
+}
+
+impl IdleTimer {
+	fn deadline(&self) -> Instant {
+		self.origin
+			+ Duration::from_millis(self.last_active_ms.load(Ordering::Relaxed))
+			+ self.timeout
+	}
+
+	fn touch(&self) {
+		let elapsed = self.origin.elapsed().as_millis() as u64;
+		self.last_active_ms.fetch_max(elapsed, Ordering::Relaxed);
+	}
+}
+
 /// Token for cooperative cancellation of blocking work.
 ///
 /// Call `heartbeat()` periodically inside long-running work to check for
-/// cancellation requests from timeouts or abort signals.
+/// cancellation requests from timeouts or abort signals. A fixed timeout and
+/// an idle timeout (see [`CancelToken::with_idle_timeout`]) can be active at
+/// once; whichever expires first cancels the work.
 #[derive(Clone, Default)]
 pub struct CancelToken {
 	deadline: Option<Instant>,
+	idle:     Option<Arc<IdleTimer>>,
 	flag:     Option<Arc<Flag>>,
 }
 
@@ -138,6 +165,36 @@ impl CancelToken {
 		result
 	}
 
+	/// Add an idle timeout that fires `idle_timeout_ms` after the last
+	/// [`reset_idle`](Self::reset_idle) call (or after now, if never reset).
+	/// Clones share the idle timer.
+	#[must_use]
+	pub fn with_idle_timeout(mut self, idle_timeout_ms: u32) -> Self {
+		self.idle = Some(Arc::new(IdleTimer {
+			timeout:        Duration::from_millis(idle_timeout_ms as u64),
+			origin:         Instant::now(),
+			last_active_ms: AtomicU64::new(0),
+		}));
+		self
+	}
+
+	/// Record activity, pushing the idle deadline back by the full idle
+	/// timeout. No-op without an idle timeout.
+	pub fn reset_idle(&self) {
+		if let Some(idle) = &self.idle {
+			idle.touch();
+		}
+	}
+
+	/// Earliest of the fixed and idle deadlines.
+	fn effective_deadline(&self) -> Option<Instant> {
+		let idle = self.idle.as_ref().map(|idle| idle.deadline());
+		match (self.deadline, idle) {
+			(Some(fixed), Some(idle)) => Some(fixed.min(idle)),
+			(fixed, idle) => fixed.or(idle),
+		}
+	}
+
 	/// Check if cancellation has been requested.
 	///
 	/// Returns `Ok(())` if work should continue, or an error if cancelled.
@@ -146,16 +203,31 @@ impl CancelToken {
 		if let Some(flag) = &self.flag
 			&& let Some(reason) = flag.cause()
 		{
-			return Err(Error::from_reason(format!("Aborted: {reason:?}")));
+			let code = if matches!(reason, AbortReason::Timeout) {
+				ErrorCode::Timeout
+			} else {
+				ErrorCode::Aborted
+			};
+			return Err(error::coded(code, format!("Aborted: {reason:?}")));
 		}
-		if let Some(deadline) = self.deadline
+		if let Some(deadline) = self.effective_deadline()
 			&& deadline < Instant::now()
 		{
-			return Err(Error::from_reason("Aborted: Timeout"));
+			return Err(error::coded(ErrorCode::Timeout, "Aborted: Timeout"));
 		}
 		Ok(())
 	}
 
+	/// Milliseconds left before the timeout (or idle timeout) fires, or `None`
+	/// without a deadline. Returns `Some(0)` once the deadline has passed.
+	pub fn remaining_ms(&self) -> Option<u64> {
+		self.effective_deadline().map(|deadline| {
+			deadline
+				.saturating_duration_since(Instant::now())
+				.as_millis() as u64
+		})
+	}
+
 	/// Wait for the cancel token to be aborted.
 	pub async fn wait(&self) -> AbortReason {
 		let flag = self.flag.as_ref();
@@ -170,11 +242,19 @@ impl CancelToken {
 		};
 
 		let fttl = async {
-			let Some(ttl) = self.deadline else {
-				return std::future::pending().await;
-			};
-			tokio::time::sleep_until(ttl.into()).await;
-			AbortReason::Timeout
+			loop {
+				let Some(ttl) = self.effective_deadline() else {
+					return std::future::pending().await;
+				};
+				tokio::time::sleep_until(ttl.into()).await;
+				// Activity may have moved the idle deadline while sleeping.
+				if self
+					.effective_deadline()
+					.is_some_and(|deadline| deadline <= Instant::now())
+				{
+					return AbortReason::Timeout;
+				}
+			}
 		};
 
 		let fuser = async {
@@ -208,7 +288,7 @@ impl CancelToken {
 		{
 			return true;
 		}
-		if let Some(deadline) = self.deadline
+		if let Some(deadline) = self.effective_deadline()
 			&& deadline < Instant::now()
 		{
 			return true;
@@ -268,6 +348,10 @@ where
 	fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
 		Ok(output)
 	}
+
+	fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+		Err(error::to_js_error(&env, err))
+	}
 }
 
 pub type Async<T> = AsyncTask<Blocking<T>>;
@@ -341,8 +425,12 @@ where
 	Fut: Future<Output = Result<T>> + Send + 'static,
 	T: ToNapiValue + Send + 'static,
 {
-	env.spawn_future(async move {
-		let _guard = profile_region(tag);
-		work.await
-	})
+	env.spawn_future_with_callback(
+		async move {
+			let _guard = profile_region(tag);
+			Ok(work.await)
+		},
+		// Errors are settled on the JS thread so coded errors get their `code`.
+		|env, result| result.map_err(|err| error::to_js_error(env, err)),
+	)
 }
@@ -6,6 +6,27 @@
 /** Callback type for threadsafe functions from N-API. */
 export type TsFunc<T> = (error: Error | null, value: T) => void;
 
+/** Stable `code` values attached to errors thrown by native bindings. */
+export type NativeErrorCode =
+	| "PATH_NOT_FOUND"
+	| "NOT_A_DIRECTORY"
+	| "INVALID_PATTERN"
+	| "TIMEOUT"
+	| "ABORTED"
+	| "PTY_NOT_RUNNING"
+	| "PTY_ALREADY_RUNNING"
+	| "PTY_FAILURE"
+	| "IO"
+	| "NOT_A_GIT_REPO"
+	| "PATH_OUTSIDE_BASE"
+	| "PTY_LIMIT_REACHED"
+	| "NO_MATCH";
+
+/** Error thrown by native bindings, carrying a {@link NativeErrorCode}. */
+export interface NativeError extends Error {
+	code?: NativeErrorCode;
+}
+
 /** Options for cancellable operations. */
 export interface Cancellable {
 	/** Timeout in milliseconds for the operation. */
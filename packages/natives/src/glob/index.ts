@@ -4,21 +4,53 @@
 
 import * as path from "node:path";
 import { native } from "../native";
-import type { GlobMatch, GlobOptions, GlobResult } from "./types";
+import type {
+	CompilePatternOptions,
+	FileType,
+	FsScanCacheEviction,
+	FsScanCacheStats,
+	FsScanCacheStatus,
+	GlobHandle,
+	GlobMatch,
+	GlobOptions,
+	GlobResult,
+	ListPathsOptions,
+	PrecompileGlobsOptions,
+	SelfTestReport,
+} from "./types";
 
-export type { GlobMatch, GlobOptions, GlobResult } from "./types";
+export type {
+	CompilePatternOptions,
+	ExtensionCounts,
+	FsScanCacheEviction,
+	FsScanCacheStats,
+	FsScanCacheStatus,
+	GlobGroup,
+	GlobHandle,
+	GlobMatch,
+	GlobOptions,
+	GlobResult,
+	GlobTimeBucket,
+	GlobTreeNode,
+	ListPathsOptions,
+	PrecompileGlobsOptions,
+	SelfTestReport,
+} from "./types";
 export { FileType } from "./types";
 
+/** Convert simple patterns to recursive globs if needed. */
+function toRecursivePattern(pattern: string): string {
+	const effective = pattern || "*";
+	return effective.includes("/") || effective.startsWith("**") ? effective : `**/${effective}`;
+}
+
 /**
  * Find files matching a glob pattern.
  * Respects .gitignore by default.
  */
 export async function glob(options: GlobOptions, onMatch?: (match: GlobMatch) => void): Promise<GlobResult> {
 	const searchPath = path.resolve(options.path);
-	const pattern = options.pattern || "*";
-
-	// Convert simple patterns to recursive globs if needed
-	const globPattern = pattern.includes("/") || pattern.startsWith("**") ? pattern : `**/${pattern}`;
+	const globPattern = toRecursivePattern(options.pattern);
 
 	// napi-rs ThreadsafeFunction passes (error, value) - skip callback on error
 	const cb = onMatch ? (err: Error | null, m: GlobMatch) => !err && onMatch(m) : undefined;
@@ -35,6 +67,84 @@ export async function glob(options: GlobOptions, onMatch?: (match: GlobMatch) =>
 	);
 }
 
+/**
+ * Decode a `format: "buffer"` result into matches. Each record is a little-endian `u32` path byte length,
+ * the UTF-8 path, a `u8` file type, and an `f64` mtime (`NaN` when unknown).
+ */
+export function decodeGlobBuffer(buffer: Uint8Array): GlobMatch[] {
+	const view = new DataView(buffer.buffer, buffer.byteOffset, buffer.byteLength);
+	const decoder = new TextDecoder();
+	const matches: GlobMatch[] = [];
+	let offset = 0;
+	while (offset < buffer.byteLength) {
+		const length = view.getUint32(offset, true);
+		offset += 4;
+		const path = decoder.decode(buffer.subarray(offset, offset + length));
+		offset += length;
+		const fileType = view.getUint8(offset) as FileType;
+		const mtime = view.getFloat64(offset + 1, true);
+		offset += 9;
+		matches.push(Number.isNaN(mtime) ? { path, fileType } : { path, fileType, mtime });
+	}
+	return matches;
+}
+
+/**
+ * Compile a glob pattern once for repeated `globWith` queries.
+ */
+export function compilePattern(options: CompilePatternOptions): GlobHandle {
+	return native.compilePattern({ ...options, pattern: toRecursivePattern(options.pattern) });
+}
+
+/**
+ * Compile a batch of glob patterns up front (e.g. at startup); handles are returned in input order.
+ */
+export function precompileGlobs(patterns: string[], options?: PrecompileGlobsOptions): GlobHandle[] {
+	return native.precompileGlobs(patterns.map(toRecursivePattern), options);
+}
+
+/**
+ * Find files matching a precompiled glob pattern.
+ * `options.pattern` is ignored in favor of the handle's pattern.
+ */
+export async function globWith(
+	handle: GlobHandle,
+	options: GlobOptions,
+	onMatch?: (match: GlobMatch) => void,
+): Promise<GlobResult> {
+	const cb = onMatch ? (err: Error | null, m: GlobMatch) => !err && onMatch(m) : undefined;
+
+	return native.globWith(
+		handle,
+		{
+			...options,
+			path: path.resolve(options.path),
+			hidden: options.hidden ?? false,
+			gitignore: options.gitignore ?? true,
+		},
+		cb,
+	);
+}
+
+/**
+ * Match a glob pattern against an in-memory entry list (walker order) instead of the filesystem.
+ * Applies the same filters, ordering, and grouping as `glob`; scan and cache options are ignored.
+ */
+export function globMatchEntries(
+	entries: GlobMatch[],
+	options: Omit<GlobOptions, "path"> & { path?: string },
+): GlobResult {
+	return native.globMatchEntries(entries, { ...options, path: options.path ?? "" });
+}
+
+/**
+ * List the relative path of every scanned entry under `path`, in walk order, without any pattern matching.
+ * Served from the shared scan cache, scanning on a miss; for ranking the whole index on the JS side.
+ */
+export async function listAllPaths(searchPath: string, options?: ListPathsOptions): Promise<string[]> {
+	return native.listAllPaths(path.resolve(searchPath), options);
+}
+
 /**
  * Invalidate the filesystem scan cache.
  *
@@ -44,3 +154,94 @@ export async function glob(options: GlobOptions, onMatch?: (match: GlobMatch) =>
 export function invalidateFsScanCache(path?: string): void {
 	native.invalidateFsScanCache(path);
 }
+
+/**
+ * Update the filesystem scan cache after a rename.
+ *
+ * Cached scans under (or containing) `from` are relocated to `to` rather than
+ * discarded. Only valid for pure relocations with unchanged contents.
+ */
+export function renameFsScanCache(from: string, to: string): void {
+	native.renameFsScanCache(from, to);
+}
+
+/**
+ * Check whether the ignore rules `glob` applies with `gitignore: true` would exclude `path`, without walking.
+ * `path` may be relative to `root` or absolute, and need not exist.
+ */
+export function isIgnored(root: string, path: string): boolean {
+	return native.isIgnored(root, path);
+}
+
+/**
+ * Report the age and size of the cached scan of `path` for the given settings and cache namespace.
+ * Returns null when no unexpired scan is cached.
+ */
+export function fsScanCacheStatus(
+	path: string,
+	hidden?: boolean,
+	gitignore?: boolean,
+	namespace?: string,
+): FsScanCacheStatus | null {
+	return native.fsScanCacheStatus(path, hidden, gitignore, namespace);
+}
+
+/**
+ * Report the entry count and estimated memory of the whole scan cache, across namespaces,
+ * against its `FS_SCAN_CACHE_MAX_BYTES` ceiling.
+ */
+export function fsScanCacheStats(): FsScanCacheStats {
+	return native.fsScanCacheStats();
+}
+
+/**
+ * Drop every cached scan older than `FS_SCAN_CACHE_TTL_MS`, across namespaces; returns how many were removed.
+ * Long-lived processes can call this periodically to release scans of roots they no longer query.
+ */
+export function pruneFsScanCache(): number {
+	return native.pruneFsScanCache();
+}
+
+/**
+ * Keep the scan of `path` for the given settings and cache namespace cached past the
+ * `FS_SCAN_CACHE_MAX_ENTRIES` cap, evicting unpinned scans first. Lasts until `unpinFsScanCache`.
+ */
+export function pinFsScanCache(path: string, hidden?: boolean, gitignore?: boolean, namespace?: string): void {
+	native.pinFsScanCache(path, hidden, gitignore, namespace);
+}
+
+/**
+ * Remove a pin added by `pinFsScanCache` with the same arguments; returns whether one was removed.
+ */
+export function unpinFsScanCache(path: string, hidden?: boolean, gitignore?: boolean, namespace?: string): boolean {
+	return native.unpinFsScanCache(path, hidden, gitignore, namespace);
+}
+
+/**
+ * Observe scan cache evictions (root, age, entry count, and which limit forced them out), e.g. to tune
+ * `FS_SCAN_CACHE_MAX_ENTRIES`. Replaces any previous listener; call without one to remove it.
+ * The listener does not keep the process alive.
+ */
+export function onFsScanCacheEvict(listener?: (eviction: FsScanCacheEviction) => void): void {
+	native.onFsScanCacheEvict(listener ? (err, eviction) => !err && listener(eviction) : null);
+}
+
+/**
+ * Dump the entries of the cached scan of `path` for the given settings and cache namespace, in walk order.
+ * Returns null when no unexpired scan is cached.
+ */
+export function fsScanCacheEntriesForRoot(
+	path: string,
+	hidden?: boolean,
+	gitignore?: boolean,
+	namespace?: string,
+): GlobMatch[] | null {
+	return native.fsScanCacheEntriesForRoot(path, hidden, gitignore, namespace);
+}
+
+/**
+ * Smoke-test the native module by walking and globbing a temp directory.
+ */
+export function nativeSelfTest(): SelfTestReport {
+	return native.nativeSelfTest();
+}
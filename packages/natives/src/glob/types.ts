@@ -15,12 +15,14 @@ export const enum FileType {
 
 /** Options for discovering files and directories. */
 export interface GlobOptions extends Cancellable {
-	/** Glob pattern to match (e.g., `*.ts`). */
+	/** Glob pattern to match (e.g., `*.ts`); a trailing slash, as in `build/`, matches only directories. */
 	pattern: string;
 	/** Directory to search. */
 	path: string;
 	/** Filter by file type: "file", "dir", or "symlink". */
 	fileType?: FileType;
+	/** Keep entries of any of these types (e.g. files and symlinks, not dirs); merged with `fileType`, and an empty list applies no filter. */
+	fileTypes?: FileType[];
 	/** Include hidden files (default: false). */
 	hidden?: boolean;
 	/** Maximum number of results to return. */
@@ -31,8 +33,191 @@ export interface GlobOptions extends Cancellable {
 	cache?: boolean;
 	/** Sort results by mtime (most recent first) before applying limit. */
 	sortByMtime?: boolean;
+	/** Direction of the active sort (default: "desc"); ties break by path in the same direction. */
+	sortDirection?: "asc" | "desc";
 	/** Include node_modules entries even when pattern does not mention node_modules. */
 	includeNodeModules?: boolean;
+	/** Report each physical file once, skipping paths that resolve to an already-seen inode (default: false). */
+	dedupByInode?: boolean;
+	/** Only stream matches through the callback; `matches` is left empty and only `totalMatches` is set (default: false). */
+	streamOnly?: boolean;
+	/** Treat backslashes in the pattern as path separators on every platform (default: false; always on for Windows). */
+	normalizeSeparators?: boolean;
+	/** Wildcard semantics: `*` crosses `/` by default; `"bash"` keeps `*` and `?` within one path component. */
+	globSyntax?: "default" | "bash" | "literal-star-crosses-dirs";
+	/** Only match directories with no descendants in the scan (default: false). */
+	emptyDirsOnly?: boolean;
+	/** Relative match paths to rank first, in list order (applied after `sortByMtime`, before `maxResults`). */
+	priorityPaths?: string[];
+	/** Store the fresh scan of an uncached (`cache: false`) query for later cached queries (default: false). */
+	populateCache?: boolean;
+	/** Relative paths or globs to add back even when ignore rules exclude them; they must still match `pattern`. Globs must start with a literal directory (`dist/**/*.js`); use `reinclude` for patterns at any depth. */
+	forceInclude?: string[];
+	/** Stop scanning after this many entries, regardless of time; see `scanCapped` on the result. */
+	maxScanEntries?: number;
+	/** Return the ancestor directories (up to the root) of files matching `pattern` instead of the files (default: false). */
+	dirsWithMatches?: boolean;
+	/** Skip matches whose relative path exceeds this many characters; see `skippedLongPaths` on the result. */
+	maxPathLength?: number;
+	/** Only match entries exactly this many directories below the root (0 = immediate children). */
+	exactDepth?: number;
+	/** Accept a file as `path`, returning it (by name) if it matches instead of throwing `NOT_A_DIRECTORY` (default: false). */
+	allowFileRoot?: boolean;
+	/** Only match entries modified at or after this time (ms since epoch), e.g. a previous `snapshotMs`. */
+	newerThanSnapshotMs?: number;
+	/** Include `snapshotMs` in the result (default: false). */
+	returnSnapshot?: boolean;
+	/** Return matches bucketed by first path component in `groups` instead of `matches` (default: false). */
+	groupByTopDir?: boolean;
+	/** With `groupByTopDir`: keep at most this many matches per group, applied after `maxResults`. */
+	maxResultsPerGroup?: number;
+	/** Accept at most this many matches per parent directory, in walk order, to sample across the tree. */
+	maxResultsPerDir?: number;
+	/** Keep only the first match per file name, in walk order (or the active sort order), dropping same-named matches elsewhere. */
+	uniqueByBasename?: boolean;
+	/** Replace a directory's matches with one entry (see `collapsedCount`) when more than this many would be listed there. */
+	collapseThreshold?: number;
+	/** Stop collecting matches once their paths would total more than this many bytes; see `truncated`. */
+	maxResultBytes?: number;
+	/** Only match files of this language (e.g. "rust", "typescript"), using the same table as grep's `type`. */
+	language?: string;
+	/** Extensions (without the dot) to use for `language` instead of the built-in table; also usable alone. */
+	languageExtensions?: string[];
+	/** Fuzzy query scored against each entry's name and path instead of `pattern`; results rank best score first. */
+	fuzzy?: string;
+	/** Return matches nested by path component under `tree` instead of a flat list (default: false). */
+	asTree?: boolean;
+	/** On `timeoutMs` expiry, reject (`"error"`, default) or resolve with the matches found so far (`"partial"`). */
+	timeoutBehavior?: "error" | "partial";
+	/** Regex the file name (final path component) must also match, applied after `pattern`. */
+	nameRegex?: string;
+	/** Cache namespace with its own entry cap, so unrelated callers don't evict each other's scans (default: shared). */
+	cacheNamespace?: string;
+	/** Descend into symlinked directories (default: false); loops are skipped and counted in `symlinkLoopsDetected`. */
+	followSymlinks?: boolean;
+	/** With `followSymlinks`, stop descending once a path has crossed this many symlinks (default: unlimited). */
+	maxSymlinkDepth?: number;
+	/** Walk the tree on multiple threads (default: false); results keep the sequential order. */
+	parallel?: boolean;
+	/** Worker threads for `parallel` walks (default: 0, chosen automatically). */
+	threads?: number;
+	/** Only match files `git status` reports as modified, staged, or untracked (default: false); throws `NOT_A_GIT_REPO` outside a repository. */
+	onlyModifiedInGit?: boolean;
+	/** Fingerprint the returned matches in `resultHash` (default: false). */
+	resultHash?: boolean;
+	/** Set `childCount` on directory matches to their number of scanned immediate children (default: false). */
+	includeChildCount?: boolean;
+	/** Invoke the match callback at most once per this many ms, skipping callbacks in between; `matches` still lists every match. */
+	callbackMinIntervalMs?: number;
+	/** Set `orderIndex` on each match to its position in the scan's walk order, to see which entries `maxResults` kept (default: false). */
+	includeOrderIndex?: boolean;
+	/** Only match files git tracks, excluding untracked and ignored files (default: false); throws `NOT_A_GIT_REPO` outside a repository. */
+	onlyGitTracked?: boolean;
+	/** Prefix slash-free patterns with `**\/` to match at any depth (default: true); when false, `*.rs` matches only direct children of the root (so `exactDepth` above 0 matches nothing). */
+	autoRecursive?: boolean;
+	/** Result shape: `"objects"` (default) fills `matches`; `"paths"` returns newline-joined relative paths in `paths`; `"buffer"` packs matches into `buffer` for `decodeGlobBuffer`. Both leave `matches` empty. */
+	format?: "objects" | "paths" | "buffer";
+	/** Match patterns against each entry's absolute, symlink-resolved path instead of its relative path (default: false); patterns should be absolute or start with `**`. Ignored by `globMatchEntries`. */
+	matchResolvedPath?: boolean;
+	/** Only keep symlinks whose target is missing, to find dangling links (default: false). Ignored by `globMatchEntries`. */
+	brokenSymlinksOnly?: boolean;
+	/** Move directories ahead of files and symlinks (`false`: behind them), keeping the existing order within each group; unset leaves the order alone. */
+	dirsFirst?: boolean;
+	/** Count scanned and matched files and symlinks per extension into the result's `extensionBreakdown`; filtering then visits every entry instead of stopping at `maxResults`. */
+	extensionBreakdown?: boolean;
+	/** Trust an absolute `path` as an existing canonical directory and skip resolving it; a missing path then yields an empty result instead of `PATH_NOT_FOUND`, and `allowFileRoot` no longer applies. */
+	rootAlreadyResolved?: boolean;
+	/** Set `discoveredAtMs` on each match to when the walk found it, relative to the walk's start; only fresh scans carry stamps, cached matches have none. */
+	includeDiscoveredAtMs?: boolean;
+	/** Called with the number of entries discovered so far, every 1000 entries, while a cache miss walks the tree. */
+	onScanProgress?: TsFunc<number>;
+	/** Respect `.piignore` files, in `.gitignore` syntax, on top of `gitignore` (default: true). */
+	usePiignore?: boolean;
+	/** Return paths with `\` separators on Windows; matching still uses `/` (default: false). */
+	nativeSeparators?: boolean;
+	/** Bucket matches by mtime into the result's `timeBuckets` (`matches` is then empty), newest first; implies descending `sortByMtime`. */
+	timeBuckets?: boolean;
+	/** Set `realPath` on each match to its absolute, symlink-resolved path; costs a `realpath` per match (default: false). */
+	canonicalize?: boolean;
+	/** Throw a `NO_MATCH` error instead of returning an empty result, like `shopt -s failglob`; partial results after a timeout are still returned. */
+	requireMatch?: boolean;
+	/** Keep only regular files whose header sniffs as this type (`"binary"` is anything but text); costs a 512-byte read per candidate. */
+	contentType?: "image" | "archive" | "pdf" | "text" | "binary";
+	/** Drop matches below another matched directory, leaving a minimal covering set; filtering then visits every entry instead of stopping at `maxResults`. */
+	topmostOnly?: boolean;
+	/** Keep only matches this predicate returns `true` for; runs after every native filter since each call crosses into JS. Ignored by `globMatchEntries`. */
+	filter?: (match: GlobMatch) => boolean;
+	/** Stay on the root's filesystem: directories on other mounts (e.g. NFS/SMB shares) are listed but not entered. */
+	sameFilesystem?: boolean;
+	/** Gitignore-style patterns (`"dist/"` or `"!dist/"`) whose ignored matches are added back, as negations appended to `.gitignore` would; part of the scan cache key. */
+	reinclude?: string[];
+	/** Set `depth` on each match to the number of directories between it and the root, as `exactDepth` counts them. */
+	includeDepth?: boolean;
+	/** Prefix every output path with this name and a separator (e.g. `"@workspace"` gives `"@workspace/src/a.rs"`); matching and the cache are unaffected. */
+	rootAlias?: string;
+	/** Set `patternIndex` on each match to the index of the pattern that matched it; `pattern` is a single glob, so this is always 0. */
+	includeMatchedPatternIndex?: boolean;
+}
+
+/** Options for precompiling a glob pattern. */
+export interface CompilePatternOptions {
+	/** Glob pattern to compile (e.g., `*.ts`). */
+	pattern: string;
+	/** Treat backslashes in the pattern as path separators on every platform (default: false; always on for Windows). */
+	normalizeSeparators?: boolean;
+	/** Wildcard semantics: `*` crosses `/` by default; `"bash"` keeps `*` and `?` within one path component. */
+	globSyntax?: "default" | "bash" | "literal-star-crosses-dirs";
+	/** Match slash-free patterns at any depth, as for `glob` (default: true). */
+	autoRecursive?: boolean;
+}
+
+/** Options applied to every pattern `precompileGlobs` compiles, as for `compilePattern`. */
+export type PrecompileGlobsOptions = Omit<CompilePatternOptions, "pattern">;
+
+/** Options for `listAllPaths`. */
+export interface ListPathsOptions extends Cancellable {
+	/** Include hidden files (default: false). */
+	hidden?: boolean;
+	/** Respect .gitignore files (default: true). */
+	gitignore?: boolean;
+	/** Include `node_modules` entries (default: false). */
+	includeNodeModules?: boolean;
+}
+
+/** Precompiled glob pattern, reusable across `globWith` calls. */
+export interface GlobHandle {
+	/** The normalized pattern this handle was compiled from. */
+	readonly pattern: string;
+}
+
+/** Matches sharing a first path component. */
+export interface GlobGroup {
+	/** First path component of every match in the group (`""` for files directly under the root). */
+	dir: string;
+	/** Matches in result order. */
+	matches: GlobMatch[];
+}
+
+/** Matches whose mtime falls in the same `timeBuckets` window. */
+export interface GlobTimeBucket {
+	/** `"today"` (last 24 hours), `"yesterday"` (24 to 48 hours ago), `"thisWeek"` (last 7 days), or `"older"` (including no mtime). */
+	bucket: "today" | "yesterday" | "thisWeek" | "older";
+	/** Matches in result order, newest first. */
+	matches: GlobMatch[];
+}
+
+/** A node of the `asTree` result, rooted at the search root. */
+export interface GlobTreeNode {
+	/** Final path component (`""` for the root). */
+	name: string;
+	/** Relative path from the search root (`""` for the root). */
+	path: string;
+	/** Filesystem type; intermediate directories are `Dir`. */
+	fileType: FileType;
+	/** Whether this node is itself a match rather than only an ancestor of one. */
+	matched: boolean;
+	/** Child nodes in result order. */
+	children: GlobTreeNode[];
 }
 
 /** A single filesystem match. */
@@ -43,14 +228,110 @@ export interface GlobMatch {
 	fileType: FileType;
 	/** Modification time in milliseconds since epoch, if available. */
 	mtime?: number;
+	/** Number of matches this directory entry summarizes, when `collapseThreshold` collapsed them. */
+	collapsedCount?: number;
+	/** Fuzzy match score (higher is better), when `fuzzy` is set. */
+	score?: number;
+	/** Number of scanned immediate children of this directory, when `includeChildCount` is set. */
+	childCount?: number;
+	/** Position of this entry in the scan's walk order, when `includeOrderIndex` is set. */
+	orderIndex?: number;
+	/** Milliseconds from the start of the walk until it found this entry, when `includeDiscoveredAtMs` is set and the scan was fresh. */
+	discoveredAtMs?: number;
+	/** Absolute, symlink-resolved path, when `canonicalize` is set and resolution succeeded. */
+	realPath?: string;
+	/** Directories between the root and this entry (0 = immediate child), when `includeDepth` is set. */
+	depth?: number;
+	/** Index of the pattern that matched this entry, when `includeMatchedPatternIndex` is set. */
+	patternIndex?: number;
 }
 
 /** Result of a find operation. */
 export interface GlobResult {
 	/** Matched filesystem entries. */
 	matches: GlobMatch[];
-	/** Number of matches returned after limits are applied. */
+	/** Number of matches accepted after limits are applied (equals `matches.length` unless `streamOnly` is set). */
 	totalMatches: number;
+	/** Whether the empty-result recheck of a stale cached scan was skipped because little timeout budget remained. */
+	rescanSkippedDueToBudget: boolean;
+	/** Whether the scan stopped at `maxScanEntries`, so matches may be incomplete. */
+	scanCapped: boolean;
+	/** Whether `matches` came from a cached scan rather than a fresh walk (uncached query or empty-result rescan). */
+	fromCache: boolean;
+	/** Number of otherwise-matching entries skipped by `maxPathLength`. */
+	skippedLongPaths: number;
+	/** Whether `matches` stopped early at `maxResultBytes`. */
+	truncated: boolean;
+	/** With `returnSnapshot`: time (ms since epoch) the scanned entries reflect; pass as the next `newerThanSnapshotMs`. */
+	snapshotMs?: number;
+	/** Matches bucketed by first path component when `groupByTopDir` is set (`matches` is then empty). */
+	groups?: GlobGroup[];
+	/** Matches nested by path component when `asTree` is set (`matches` is then empty). */
+	tree?: GlobTreeNode;
+	/** Whether `timeoutBehavior: "partial"` cut the query short, so `matches` may be incomplete. */
+	timedOut: boolean;
+	/** Number of symlink loops skipped while walking with `followSymlinks`. */
+	symlinkLoopsDetected: number;
+	/** Whether any `.gitignore` or `.git/info/exclude` rules were found and applied (false outside a git repository). */
+	gitignoreApplied: boolean;
+	/** With `resultHash`: hex fingerprint of matched paths, types, and mtimes, independent of order; equal hashes mean the same file set. */
+	resultHash?: string;
+	/** With `format: "paths"`: matched relative paths in result order, one per line. */
+	paths?: string;
+	/** With `extensionBreakdown`: counts keyed by extension without the dot (`""` for none); directories are not counted. */
+	extensionBreakdown?: Record<string, ExtensionCounts>;
+	/** Matches bucketed by mtime when `timeBuckets` is set (`matches` is then empty); empty buckets are omitted. */
+	timeBuckets?: GlobTimeBucket[];
+	/** With `format: "buffer"`: matches in result order, packed for `decodeGlobBuffer`. */
+	buffer?: Buffer;
+}
+
+/** Per-extension entry counts from `extensionBreakdown`. */
+export interface ExtensionCounts {
+	/** Entries with this extension that reached the filters. */
+	scanned: number;
+	/** Entries with this extension that passed every filter, before `maxResults` truncation. */
+	matched: number;
+}
+
+/** Freshness of a cached filesystem scan. */
+export interface FsScanCacheStatus {
+	/** Age of the cached scan in milliseconds. */
+	ageMs: number;
+	/** Number of entries in the cached scan. */
+	entryCount: number;
+}
+
+/** Size of the whole filesystem scan cache, across namespaces. */
+export interface FsScanCacheStats {
+	/** Number of cached scans. */
+	entryCount: number;
+	/** Estimated memory held by all cached scans, in bytes. */
+	totalBytes: number;
+	/** Ceiling on `totalBytes` (`FS_SCAN_CACHE_MAX_BYTES`); the oldest scans are evicted beyond it. */
+	maxTotalBytes: number;
+}
+
+/** A scan dropped from the cache to make room, as reported to `onFsScanCacheEvict`. */
+export interface FsScanCacheEviction {
+	/** Root directory of the evicted scan. */
+	root: string;
+	/** Age of the evicted scan in milliseconds. */
+	ageMs: number;
+	/** Number of entries in the evicted scan. */
+	entryCount: number;
+	/** Which limit forced it out: the namespace's `FS_SCAN_CACHE_MAX_ENTRIES` or the global `FS_SCAN_CACHE_MAX_BYTES`. */
+	reason: "entryCap" | "byteBudget";
+}
+
+/** Outcome of the native self-test. */
+export interface SelfTestReport {
+	/** Whether the walk and glob produced the expected entries. */
+	ok: boolean;
+	/** Number of entries the walker discovered in the probe directory. */
+	walkedEntries: number;
+	/** Failure description when `ok` is false. */
+	error?: string;
 }
 
 declare module "../bindings" {
@@ -61,7 +342,53 @@ declare module "../bindings" {
 		 * @param onMatch Optional callback for streaming matches as they are found.
 		 */
 		glob(options: GlobOptions, onMatch?: TsFunc<GlobMatch>): Promise<GlobResult>;
+		/** Compile a glob pattern once for repeated `globWith` queries. */
+		compilePattern(options: CompilePatternOptions): GlobHandle;
+		/** Compile a batch of glob patterns up front; handles are returned in input order. */
+		precompileGlobs(patterns: string[], options?: PrecompileGlobsOptions): GlobHandle[];
+		/**
+		 * Find filesystem entries matching a precompiled glob pattern.
+		 * `options.pattern`, `options.normalizeSeparators`, `options.globSyntax`, and `options.autoRecursive` are ignored.
+		 */
+		globWith(handle: GlobHandle, options: GlobOptions, onMatch?: TsFunc<GlobMatch>): Promise<GlobResult>;
+		/**
+		 * Match a glob pattern against caller-supplied entries without touching the filesystem.
+		 * Scan and cache options (including `path`) are ignored.
+		 */
+		globMatchEntries(entries: GlobMatch[], options: GlobOptions): GlobResult;
+		/** Relative path of every scanned entry under `path`, in walk order, from the shared scan cache. */
+		listAllPaths(path: string, options?: ListPathsOptions): Promise<string[]>;
+		/** Walk a temp directory and glob it to verify the native module works end to end. */
+		nativeSelfTest(): SelfTestReport;
 		/** Invalidate the filesystem scan cache for the given path (or all caches if omitted). */
 		invalidateFsScanCache(path?: string): void;
+		/** Relocate cached scans after renaming `from` to `to` instead of discarding them. */
+		renameFsScanCache(from: string, to: string): void;
+		/** Age and size of the cached scan of `path` for these settings, or null if none is cached. */
+		fsScanCacheStatus(
+			path: string,
+			hidden?: boolean,
+			gitignore?: boolean,
+			namespace?: string,
+		): FsScanCacheStatus | null;
+		/** Entry count and estimated bytes of the whole scan cache. */
+		fsScanCacheStats(): FsScanCacheStats;
+		/** Drop every expired cached scan; returns how many were removed. */
+		pruneFsScanCache(): number;
+		/** Register (or with null, remove) the process-wide listener for scan cache evictions. */
+		onFsScanCacheEvict(callback?: TsFunc<FsScanCacheEviction> | null): void;
+		/** Keep the scan of `path` for these settings cached past the per-namespace entry cap. */
+		pinFsScanCache(path: string, hidden?: boolean, gitignore?: boolean, namespace?: string): void;
+		/** Remove a pin added by `pinFsScanCache`; returns whether one was removed. */
+		unpinFsScanCache(path: string, hidden?: boolean, gitignore?: boolean, namespace?: string): boolean;
+		/** Entries of the cached scan of `path` for these settings, in walk order, or null if none is cached. */
+		fsScanCacheEntriesForRoot(
+			path: string,
+			hidden?: boolean,
+			gitignore?: boolean,
+			namespace?: string,
+		): GlobMatch[] | null;
+		/** Whether `glob`'s ignore rules (with `gitignore: true`, including `.piignore`) exclude `path` (relative to `root` or absolute). */
+		isIgnored(root: string, path: string): boolean;
 	}
 }
@@ -2,6 +2,12 @@
  * Native utilities powered by N-API.
  */
 
+// =============================================================================
+// Errors
+// =============================================================================
+
+export type { NativeError, NativeErrorCode } from "./bindings";
+
 // =============================================================================
 // Clipboard
 // =============================================================================
@@ -32,12 +38,40 @@ export {
 // =============================================================================
 
 export {
+	type CompilePatternOptions,
+	compilePattern,
+	decodeGlobBuffer,
+	type ExtensionCounts,
 	FileType,
+	type FsScanCacheEviction,
+	type FsScanCacheStats,
+	type FsScanCacheStatus,
+	fsScanCacheEntriesForRoot,
+	fsScanCacheStats,
+	fsScanCacheStatus,
+	type GlobGroup,
+	type GlobHandle,
 	type GlobMatch,
 	type GlobOptions,
 	type GlobResult,
+	type GlobTimeBucket,
+	type GlobTreeNode,
 	glob,
+	globMatchEntries,
+	globWith,
 	invalidateFsScanCache,
+	isIgnored,
+	type ListPathsOptions,
+	listAllPaths,
+	nativeSelfTest,
+	onFsScanCacheEvict,
+	pinFsScanCache,
+	type PrecompileGlobsOptions,
+	precompileGlobs,
+	pruneFsScanCache,
+	renameFsScanCache,
+	type SelfTestReport,
+	unpinFsScanCache,
 } from "./glob";
 
 // =============================================================================
@@ -117,7 +151,15 @@ export {
 // PTY execution
 // =============================================================================
 
-export { type PtyRunResult, PtySession, type PtyStartOptions } from "./pty";
+export {
+	listPtySessions,
+	type PtyChunkCallback,
+	type PtyRunResult,
+	PtySession,
+	type PtySessionInfo,
+	type PtyStartOptions,
+	type PtyStream,
+} from "./pty";
 // =============================================================================
 // Process management
 // =============================================================================
@@ -4,7 +4,26 @@
 
 import { native } from "../native";
 
-export type { PtyRunResult, PtySessionConstructor, PtyStartOptions } from "./types";
+import type { PtySessionInfo } from "./types";
+
+export type {
+	PtyChunkCallback,
+	PtyDimensions,
+	PtyInputStep,
+	PtyRunResult,
+	PtySessionConstructor,
+	PtySessionInfo,
+	PtyStartOptions,
+	PtyStream,
+} from "./types";
 
 export const { PtySession } = native;
 export type PtySession = import("./types").PtySession;
+
+/**
+ * List commands currently running in any PTY session, oldest first.
+ * Reads tracked state only; the running commands are unaffected.
+ */
+export function listPtySessions(): PtySessionInfo[] {
+	return native.listPtySessions();
+}
@@ -8,18 +8,64 @@ import type { Cancellable, TsFunc } from "../bindings";
  * Options for starting a command in a pseudo-terminal session.
  */
 export interface PtyStartOptions extends Cancellable {
-	/** Command to execute. */
-	command: string;
-	/** Working directory for command execution. */
+	/** Command to execute through `sh -lc`; exactly one of `command` and `argv` must be set. */
+	command?: string;
+	/** Program and arguments to execute directly, without a shell (no quoting or expansion). */
+	argv?: string[];
+	/** Working directory for command execution; relative to `baseDir` when that is set. */
 	cwd?: string;
+	/** Directory `cwd` is resolved against; the canonical result must stay inside it or the run rejects with `PATH_OUTSIDE_BASE`. */
+	baseDir?: string;
 	/** Environment variables for this command. */
 	env?: Record<string, string>;
+	/** Cancel after this many ms without `write()` input (reported as `timedOut`); `inputScript` steps don't count; combines with `timeoutMs`. */
+	idleTimeoutMs?: number;
 	/** PTY column count. */
 	cols?: number;
 	/** PTY row count. */
 	rows?: number;
+	/** File to write raw PTY output bytes to (truncated on start), in addition to any chunk callback; a failed write rejects with `IO` once the command exits. */
+	outputFile?: string;
+	/** Delay between output/control polls in ms (default: 16, clamped to 1-250). Lower trades CPU for latency. */
+	pollIntervalMs?: number;
+	/** Handling of invalid UTF-8 in output: U+FFFD (`"replace"`, default), nothing (`"drop"`), or `invalidUtf8Marker`. */
+	invalidUtf8?: "replace" | "drop" | "marker";
+	/** Text emitted per invalid sequence in `"marker"` mode (default: `"?"`). */
+	invalidUtf8Marker?: string;
+	/** Deliver stderr to `onChunk` tagged `"stderr"` instead of merging it into the PTY stream; needs `command` (Unix only). */
+	separateStderr?: boolean;
+	/** Called with the exit code as soon as the command exits, before trailing output is delivered. */
+	onExit?: TsFunc<number>;
+	/** Inputs written to the PTY in order once the command starts, each after its delay; interleaves with `write()`. */
+	inputScript?: PtyInputStep[];
+	/** Report the last non-blank output line as `lastLine` (e.g. a build's final status); escape sequences are kept as-is. */
+	captureLastLine?: boolean;
+	/** Output decoding: UTF-8 (default), or Latin-1 for programs that emit a single-byte codepage; each byte maps to the same code point. */
+	encoding?: "utf8" | "latin1";
+	/** Chunk delivered to `onChunk` before any command output; not written to `outputFile`. */
+	markerPrefix?: string;
+	/** Chunk delivered to `onChunk` after the command exits and all its output was delivered, even when cancelled. */
+	markerSuffix?: string;
+	/** When the `argv` program isn't found on `PATH`, resolve with exit code 127 and a `<program>: command not found` chunk instead of rejecting; shell `command`s already exit 127. */
+	notFoundAsExit?: boolean;
+}
+
+/**
+ * One timed step of a PTY `inputScript`.
+ */
+export interface PtyInputStep {
+	/** Milliseconds to wait after the previous step (or the command start). */
+	delayMs: number;
+	/** Raw input written to PTY stdin. */
+	data: string;
 }
 
+/** Stream an output chunk came from; `"stderr"` only with `separateStderr`. */
+export type PtyStream = "stdout" | "stderr";
+
+/** Receives each decoded output chunk with the stream it came from. */
+export type PtyChunkCallback = (error: Error | null, chunk: string, stream: PtyStream) => void;
+
 /**
  * Result of a PTY command run.
  */
@@ -30,20 +76,54 @@ export interface PtyRunResult {
 	cancelled: boolean;
 	/** Whether the command timed out. */
 	timedOut: boolean;
+	/** Bytes written to `outputFile`, when one was requested. */
+	bytesWritten?: number;
+	/** Last non-blank output line, when `captureLastLine` was set. */
+	lastLine?: string;
+}
+
+/**
+ * Current dimensions of an active PTY.
+ */
+export interface PtyDimensions {
+	/** PTY column count. */
+	cols: number;
+	/** PTY row count. */
+	rows: number;
 }
 
 /** Stateful PTY session instance. */
 export interface PtySession {
-	/** Start command execution and stream output while it runs. */
-	start(options: PtyStartOptions, onChunk?: TsFunc<string>): Promise<PtyRunResult>;
+	/** Start command execution and stream output while it runs; throws `PTY_FAILURE` unless exactly one of `command`/`argv` is set, and `PTY_LIMIT_REACHED` when `PTY_MAX_SESSIONS` (default 128) sessions are already running. */
+	start(options: PtyStartOptions, onChunk?: PtyChunkCallback): Promise<PtyRunResult>;
 	/** Write raw input bytes to PTY stdin. */
 	write(data: string): void;
 	/** Resize active PTY. */
 	resize(cols: number, rows: number): void;
+	/** Get the size last applied to the active PTY. */
+	size(): PtyDimensions;
+	/** Get the number of raw output bytes read from the active PTY so far. */
+	capturedBytes(): number;
+	/** Close PTY stdin so the command sees EOF; later writes are ignored. */
+	sendEof(): void;
 	/** Force-kill active command. */
 	kill(): void;
 }
 
+/** A command currently running in some PTY session. */
+export interface PtySessionInfo {
+	/** Process-unique id assigned when the command started. */
+	id: number;
+	/** OS process id of the command, when known. */
+	pid?: number;
+	/** Shell command, or the space-joined `argv`. */
+	command: string;
+	/** Milliseconds since the command started. */
+	ageMs: number;
+	/** Raw output bytes read from the PTY so far. */
+	capturedBytes: number;
+}
+
 /** Native PTY session constructor. */
 export interface PtySessionConstructor {
 	new (): PtySession;
@@ -53,5 +133,7 @@ declare module "../bindings" {
 	interface NativeBindings {
 		/** Stateful PTY session constructor for interactive terminal passthrough. */
 		PtySession: PtySessionConstructor;
+		/** List commands currently running in any PTY session, oldest first. */
+		listPtySessions(): PtySessionInfo[];
 	}
 }